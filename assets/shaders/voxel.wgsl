@@ -3,19 +3,14 @@
     mesh_view_bindings::{globals, view}
 }
 
-struct BlockInteraction {
-    x: u32,
-    y: u32,
-    z: u32,
-    face: u32,
-    value: u32,
-}
-
 @group(2) @binding(0) var array_texture: texture_2d_array<f32>;
 @group(2) @binding(1) var array_texture_sampler: sampler;
 @group(2) @binding(2) var destroy_texture: texture_2d_array<f32>;
 @group(2) @binding(3) var destroy_texture_sampler: sampler;
-@group(2) @binding(4) var<uniform> block_interaction: BlockInteraction;
+// The focused block's world position in xyz, and how it's highlighted in w
+@group(2) @binding(4) var<uniform> highlight: vec4<i32>;
+// Where fog starts in x and where it hides everything in y, in blocks from the camera
+@group(2) @binding(5) var<uniform> fog_range: vec2<f32>;
 
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
@@ -28,108 +23,72 @@ struct VertexOutput {
     @location(1) uv: vec2<f32>,
     @location(2) tex_index: u32,
     @location(3) ao: f32,
-    @location(4) interaction: u32,
+    @location(4) face: u32,
 }
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
-    let x_int = (vertex.packed >> 28) & 0xF;
-    let y_int = (vertex.packed >> 24) & 0xF;
-    let z_int = (vertex.packed >> 20) & 0xF;
-    let x = f32(x_int);
-    let y = f32(y_int);
-    let z = f32(z_int);
-    let corner = (vertex.packed >> 18) & 0x3;
-    let face = (vertex.packed >> 15) & 0x7;
-    let ao = f32((vertex.packed >> 13) & 0x3) / 3.0;  // Unpack AO from bits 13-14
-    let tex_index = vertex.packed & 0x1FFF;  // Get remaining 13 bits for tex_index
-
-    var final_pos = vec3<f32>(x, y, z);
-    
-    var uv = vec2<f32>(
-        f32(corner & 1u),
-        1.0 - f32(corner >> 1u)
-    );
-    
-    // Flip UV.x for right and back faces
-    if (face == 3u || face == 5u) {  // Right face (3) or Back face (5)
-        uv.x = 1.0 - uv.x;
-    }
-
-    // Flip UV.y for top face
-    if (face == 0u) {
-        uv.y = 1.0 - uv.y;
-    }
-
-    // Flip UV.x and UV.y for bottom face
-    if (face == 1u) {
-        uv.x = 1.0 - uv.x;
-        uv.y = 1.0 - uv.y;
-    }
-
-    // Apply offsets based on face and corner
+    let x = f32((vertex.packed >> 27) & 0x1F);
+    let y = f32((vertex.packed >> 22) & 0x1F);
+    let z = f32((vertex.packed >> 17) & 0x1F);
+    let face = (vertex.packed >> 14) & 0x7;
+    let ao = f32((vertex.packed >> 12) & 0x3) / 3.0;
+    let tex_index = vertex.packed & 0xFFF;
+
+    let position = vec3<f32>(x, y, z);
+
+    // Quads can cover several blocks, so the texture coordinates count blocks across the face
+    // and repeat the texture once per block
+    var uv: vec2<f32>;
     switch face {
-        case 0u: { // Top
-            final_pos.x += f32(corner & 1u);
-            final_pos.z += f32(corner >> 1u);
-            final_pos.y += 1.0;
-        }
-        case 1u: { // Bottom
-            final_pos.x += f32(corner & 1u);
-            final_pos.z += f32(corner >> 1u);
-        }
-        case 2u: { // Left
-            final_pos.z += f32(corner & 1u);
-            final_pos.y += f32(corner >> 1u);
-        }
-        case 3u: { // Right
-            final_pos.z += f32(corner & 1u);
-            final_pos.y += f32(corner >> 1u);
-            final_pos.x += 1.0;
-        }
-        case 4u: { // Front
-            final_pos.x += f32(corner & 1u);
-            final_pos.y += f32(corner >> 1u);
-            final_pos.z += 1.0;
-        }
-        case 5u: { // Back
-            final_pos.x += f32(corner & 1u);
-            final_pos.y += f32(corner >> 1u);
-        }
-        default: {}
+        case 0u: { uv = vec2<f32>(x, z); }   // Top
+        case 1u: { uv = vec2<f32>(-x, z); }  // Bottom
+        case 2u: { uv = vec2<f32>(z, -y); }  // Left
+        case 3u: { uv = vec2<f32>(-z, -y); } // Right
+        case 4u: { uv = vec2<f32>(x, -y); }  // Front
+        default: { uv = vec2<f32>(-x, -y); } // Back
     }
 
     var out: VertexOutput;
     let model = get_world_from_local(vertex.instance_index);
     out.clip_position = mesh_position_local_to_clip(
         model,
-        vec4<f32>(final_pos, 1.0),
+        vec4<f32>(position, 1.0),
     );
-    out.world_position = model * vec4<f32>(final_pos, 1.0);
+    out.world_position = model * vec4<f32>(position, 1.0);
     out.uv = uv;
     out.tex_index = tex_index;
     out.ao = ao;
-
-    out.interaction = select(
-        0u,
-        block_interaction.value,
-        block_interaction.x == x_int
-            && block_interaction.y == y_int
-            && block_interaction.z == z_int
-    );
+    out.face = face;
 
     return out;
 }
 
+fn face_normal(face: u32) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 1u: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 2u: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 3u: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 4u: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 0.0, -1.0); }
+    }
+}
+
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
     let ao_factor = mix(0.3, 1.0, in.ao);
-    let texture_sample = textureSample(array_texture, array_texture_sampler, in.uv, i32(in.tex_index));
+    let uv = fract(in.uv);
+    let texture_sample = textureSample(array_texture, array_texture_sampler, uv, i32(in.tex_index));
+
+    // Every chunk shares this material, so find which block in the world this pixel belongs to
+    let block_position = vec3<i32>(floor(in.world_position.xyz - face_normal(in.face) * 0.5));
+    let interaction = select(0u, u32(highlight.w), all(block_position == highlight.xyz));
 
     var destroy_overlay = vec4<f32>(0.0, 0.0, 0.0, 0.0);
-    if (in.interaction > 1u) {
-        let destroy_stage = in.interaction - 2u;
-        destroy_overlay = textureSample(destroy_texture, destroy_texture_sampler, in.uv, i32(destroy_stage));
+    if (interaction > 1u) {
+        let destroy_stage = interaction - 2u;
+        destroy_overlay = textureSample(destroy_texture, destroy_texture_sampler, uv, i32(destroy_stage));
     }
     
     // Discard fully transparent pixels
@@ -143,13 +102,13 @@ fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
     var final_color = texture_sample.rgb;
 
     // Apply destroy texture overlay if block is being broken
-    if (in.interaction > 0u) {
+    if (interaction > 0u) {
          // Create a slower, more obvious pulsating effect
         let pulse = (sin(globals.time * 4.0) * 0.1) + 1.1;
-        let interaction_factor = select(pulse, 1.0, in.interaction == 0u);
+        let interaction_factor = select(pulse, 1.0, interaction == 0u);
         final_color = final_color * ao_factor * interaction_factor;
 
-        if (in.interaction > 1u) {
+        if (interaction > 1u) {
             // Blend colors taking alpha into account
             final_color = final_color * (1.0 - destroy_overlay.a) + destroy_overlay.rgb * destroy_overlay.a;
         }
@@ -159,11 +118,9 @@ fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
 
     // Calculate fog
     let fog_color = vec3<f32>(0.3, 0.6, 0.9);
-    let fog_start = 80.0;
-    let fog_end = 90.0;
     
     let distance = length(in.world_position.xyz - view.world_position.xyz);
-    let fog_factor = clamp((distance - fog_start) / (fog_end - fog_start), 0.0, 1.0);
+    let fog_factor = clamp((distance - fog_range.x) / (fog_range.y - fog_range.x), 0.0, 1.0);
     
     final_color = mix(final_color, fog_color, fog_factor);
 
@@ -0,0 +1,7 @@
+CREATE TABLE metadata (
+    seed INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_played_at INTEGER NOT NULL,
+    generator_version INTEGER NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE quarantine (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL,
+    x INTEGER,
+    y INTEGER,
+    z INTEGER,
+    data BLOB NOT NULL,
+    error TEXT NOT NULL,
+    quarantined_at INTEGER NOT NULL
+);
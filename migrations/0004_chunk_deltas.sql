@@ -0,0 +1,9 @@
+ALTER TABLE metadata ADD COLUMN storage_mode TEXT NOT NULL DEFAULT 'full';
+
+CREATE TABLE chunk_deltas (
+    x INTEGER NOT NULL,
+    y INTEGER NOT NULL,
+    z INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (x, y, z)
+);
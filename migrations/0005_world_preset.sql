@@ -0,0 +1 @@
+ALTER TABLE metadata ADD COLUMN preset TEXT;
@@ -0,0 +1,109 @@
+//! Times how long the level generator takes per chunk, over the same fixed region every run,
+//! so that changes to the generator can be compared. Build with `--release` for numbers that
+//! mean anything.
+
+use std::{
+    env,
+    error::Error,
+    hint::black_box,
+    ops::RangeInclusive,
+    process::ExitCode,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use defaria::{
+    level::generator::{preset::WorldPreset, LevelGenerator},
+    position::ChunkPos,
+};
+
+const USAGE: &str = "\
+Usage: generator-bench [<seed> [<radius> [<rounds> [<preset>]]]]
+
+Generates every chunk within a radius of the origin, from 2 chunks below y = 0 to 4 chunks
+above, and reports how long each chunk took. Defaults to seed 12345, a radius of 8 chunks,
+3 rounds and the default preset, which can be a built-in preset or a preset file.";
+
+const DEFAULT_SEED: u32 = 12345;
+const DEFAULT_RADIUS: i32 = 8;
+const DEFAULT_ROUNDS: usize = 3;
+const LAYERS: RangeInclusive<i32> = -2..=4;
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    if args.len() > 4 {
+        return Err("too many arguments".into());
+    }
+
+    let seed = arg(args, 0, "seed", DEFAULT_SEED)?;
+    let radius = arg(args, 1, "radius", DEFAULT_RADIUS)?;
+
+    if radius < 0 {
+        return Err("<radius> can't be negative".into());
+    }
+
+    let rounds = arg(args, 2, "rounds", DEFAULT_ROUNDS)?.max(1);
+    let preset_name = args.get(3).map_or("default", String::as_str);
+    let preset = WorldPreset::load(preset_name)?;
+
+    let generator = LevelGenerator::with_preset(seed, preset);
+
+    let positions: Vec<ChunkPos> = (-radius..=radius)
+        .flat_map(|x| (-radius..=radius).map(move |z| (x, z)))
+        .flat_map(|(x, z)| LAYERS.map(move |y| ChunkPos::new(x, y, z)))
+        .collect();
+
+    println!(
+        "Generating {} chunks {rounds} times with seed {seed} and the {preset_name} preset",
+        positions.len()
+    );
+
+    let mut times = Vec::with_capacity(positions.len() * rounds);
+
+    for round in 1..=rounds {
+        let start = Instant::now();
+
+        for &pos in &positions {
+            let chunk_start = Instant::now();
+            black_box(generator.generate_chunk(black_box(pos)));
+            times.push(chunk_start.elapsed());
+        }
+
+        println!("  round {round}: {:.2?}", start.elapsed());
+    }
+
+    times.sort();
+
+    let total: Duration = times.iter().sum();
+    let percentile = |p: usize| times[(times.len() - 1) * p / 100];
+
+    println!("Per chunk:");
+    println!("  mean    {:.2?}", total / times.len() as u32);
+    println!("  median  {:.2?}", percentile(50));
+    println!("  p95     {:.2?}", percentile(95));
+    println!("  slowest {:.2?}", percentile(100));
+
+    Ok(())
+}
+
+fn arg<T: FromStr>(args: &[String], index: usize, name: &str, default: T) -> Result<T> {
+    match args.get(index) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid value `{value}` for <{name}>").into()),
+        None => Ok(default),
+    }
+}
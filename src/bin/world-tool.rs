@@ -0,0 +1,689 @@
+//! Inspects and maintains a world's `level.sqlite` without starting the game.
+//! The game shouldn't be running on the same world while this makes changes to it.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+
+use defaria::{
+    block::Block,
+    chunk::Chunk,
+    level::{
+        backup::{backups_dir, create_backup, list_backups, restore_backup, BackupSettings},
+        format::{decode_chunk, decode_chunk_delta, decode_inventory},
+        generator::{preset::WorldPreset, LevelGenerator, GENERATOR_VERSION},
+        world::{unix_time, StorageMode, LEGACY_GENERATOR_VERSION, LEGACY_SEED},
+    },
+    position::{ChunkPos, LocalPos, CHUNK_INDICES, CHUNK_SIZE},
+};
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+
+const USAGE: &str = "\
+Usage: world-tool <level.sqlite> <command>
+
+Commands:
+  info                                Show the world's seed and storage mode
+  preset                              Print the generator preset the world was created with
+  chunks                              List the positions of stored chunks
+  count                               Count stored chunks
+  histogram [<x> <y> <z>]             Count blocks in all stored chunks, or in a single chunk
+  render <min-x> <min-z> <max-x> <max-z> <surface.png> <heightmap.png>
+                                      Render a top-down map of a region, in chunk coordinates
+  prune <radius> [<x> <z>] [--yes]    Delete chunks further than a radius from a chunk column,
+                                      or without --yes, count the chunks that would be deleted
+  player                              Show the player, including their inventory
+  backups                             List the world's backups, newest first
+  backup [<retention>]                Back up the world, keeping this many backups (default 10)
+  restore <backup|latest> [--yes]     Back up the world, then replace it with one of its
+                                      backups, or without --yes, show which backup it would be";
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let [path, command, args @ ..] = &args[..] else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    // Pruning and restoring only change the world once they've been confirmed
+    let (args, confirmed) = match args {
+        [args @ .., yes] if matches!(command.as_str(), "prune" | "restore") && yes == "--yes" => {
+            (args, true)
+        }
+        _ => (args, false),
+    };
+
+    // Everything else only reads the world, so it's left exactly as it was
+    let result = match WorldFile::open(Path::new(path), confirmed).await {
+        Ok(world) => run(&world, command, args, confirmed).await,
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(world: &WorldFile, command: &str, args: &[String], confirmed: bool) -> Result<()> {
+    match (command, args) {
+        ("info", []) => {
+            println!("Seed: {}", world.seed);
+            println!("Storage: {}", world.storage);
+        }
+        ("preset", []) => println!("{}", world.generator.preset().to_ron()),
+        ("chunks", []) => {
+            for pos in world.chunk_positions().await? {
+                println!("{} {} {}", pos.x, pos.y, pos.z);
+            }
+        }
+        ("count", []) => println!("{}", world.chunk_positions().await?.len()),
+        ("histogram", []) => {
+            let positions = world.chunk_positions().await?;
+            print_histogram(world, &positions).await?;
+        }
+        ("histogram", [x, y, z]) => {
+            let pos = ChunkPos::new(parse(x, "x")?, parse(y, "y")?, parse(z, "z")?);
+            print_histogram(world, &[pos]).await?;
+        }
+        ("render", [min_x, min_z, max_x, max_z, surface, heightmap]) => {
+            let min = (parse(min_x, "min-x")?, parse(min_z, "min-z")?);
+            let max = (parse(max_x, "max-x")?, parse(max_z, "max-z")?);
+
+            if min.0 > max.0 || min.1 > max.1 {
+                return Err("the minimum corner of the region is above the maximum".into());
+            }
+
+            render(world, min, max, Path::new(surface), Path::new(heightmap)).await?;
+        }
+        ("prune", [radius]) => {
+            prune(world, parse(radius, "radius")?, (0, 0), confirmed).await?;
+        }
+        ("prune", [radius, x, z]) => {
+            let center = (parse(x, "x")?, parse(z, "z")?);
+            prune(world, parse(radius, "radius")?, center, confirmed).await?;
+        }
+        ("player", []) => print_player(world).await?,
+        ("backups", []) => {
+            let now = unix_time();
+
+            for backup in list_backups(&world.backups_dir())? {
+                println!(
+                    "{}  ({} minutes ago)",
+                    backup.path.display(),
+                    (now - backup.created_at) / 60
+                );
+            }
+        }
+        ("backup", []) => backup(world, BackupSettings::default().retention).await?,
+        ("backup", [retention]) => backup(world, parse(retention, "retention")?).await?,
+        ("restore", [backup]) => restore(world, backup, confirmed).await?,
+        _ => return Err(format!("unknown command or wrong arguments\n\n{USAGE}").into()),
+    }
+
+    Ok(())
+}
+
+fn parse<T: FromStr>(value: &str, name: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for <{name}>").into())
+}
+
+struct WorldFile {
+    path: PathBuf,
+    db: SqlitePool,
+    seed: u32,
+    generator_version: u32,
+    storage: StorageMode,
+    generator: LevelGenerator,
+}
+
+impl WorldFile {
+    /// Opens a world, read-only unless it's going to be changed. Worlds opened for writing are
+    /// brought up to date first, while read-only worlds have to be up to date already.
+    async fn open(path: &Path, writable: bool) -> Result<Self> {
+        if !path.is_file() {
+            return Err(format!("{} doesn't exist", path.display()).into());
+        }
+
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .read_only(!writable);
+
+        let db = SqlitePool::connect_with(options).await?;
+
+        if writable {
+            sqlx::migrate!().run(&db).await?;
+        } else if !is_migrated(&db).await? {
+            return Err(format!(
+                "{} is from an older version of the game, open it in the game first",
+                path.display()
+            )
+            .into());
+        }
+
+        let metadata =
+            sqlx::query!("SELECT seed, generator_version, storage_mode, preset FROM metadata")
+                .fetch_optional(&db)
+                .await?;
+
+        // Worlds that haven't been opened since metadata was added use the old fixed seed, and
+        // ones that haven't been opened since presets were added use the default preset
+        let (seed, generator_version, storage, preset) = match metadata {
+            Some(row) => {
+                let storage = row.storage_mode.parse()?;
+
+                let preset = match row.preset {
+                    Some(preset) => WorldPreset::from_ron(&preset)
+                        .map_err(|error| format!("the world's preset is corrupt ({error})"))?,
+                    None => WorldPreset::default(),
+                };
+
+                (
+                    row.seed as u32,
+                    row.generator_version as u32,
+                    storage,
+                    preset,
+                )
+            }
+            None => (
+                LEGACY_SEED,
+                LEGACY_GENERATOR_VERSION,
+                StorageMode::Full,
+                WorldPreset::default(),
+            ),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            db,
+            seed,
+            generator_version,
+            storage,
+            generator: LevelGenerator::with_preset(seed, preset),
+        })
+    }
+
+    fn backups_dir(&self) -> PathBuf {
+        backups_dir(self.path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Positions of the chunks that have a row in the database. In delta storage, these
+    /// are the chunks that have been changed since they were generated, including ones whose
+    /// changes were stored by an older version and haven't been stored in full yet.
+    async fn chunk_positions(&self) -> Result<Vec<ChunkPos>> {
+        let positions = match self.storage {
+            StorageMode::Full => sqlx::query!("SELECT x, y, z FROM chunks ORDER BY x, y, z")
+                .fetch_all(&self.db)
+                .await?
+                .into_iter()
+                .map(|row| ChunkPos::new(row.x as i32, row.y as i32, row.z as i32))
+                .collect(),
+            StorageMode::Delta => sqlx::query!(
+                "SELECT x, y, z FROM chunks UNION SELECT x, y, z FROM chunk_deltas ORDER BY x, y, z"
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| ChunkPos::new(row.x as i32, row.y as i32, row.z as i32))
+            .collect(),
+        };
+
+        Ok(positions)
+    }
+
+    /// Loads a chunk the same way the game would. In full storage, chunks that haven't been
+    /// generated yet don't exist, while in delta storage every chunk can be regenerated.
+    async fn load_chunk(&self, pos: ChunkPos) -> Result<Option<Chunk>> {
+        let row = sqlx::query!(
+            "SELECT data FROM chunks WHERE x = ? AND y = ? AND z = ?",
+            pos.x,
+            pos.y,
+            pos.z
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        if let Some(row) = row {
+            return Ok(Some(decode_chunk(&row.data)?.value));
+        }
+
+        if self.storage == StorageMode::Full {
+            return Ok(None);
+        }
+
+        // Changes stored by older versions are only the blocks that differ from the generated
+        // terrain, until the game stores them in full the next time it opens the world
+        let row = sqlx::query!(
+            "SELECT data FROM chunk_deltas WHERE x = ? AND y = ? AND z = ?",
+            pos.x,
+            pos.y,
+            pos.z
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        let mut chunk = self.generator.generate_chunk(pos);
+
+        if let Some(row) = row {
+            if self.generator_version != GENERATOR_VERSION {
+                return Err(format!(
+                    "its changes were stored against generator version {} terrain, but this \
+                    tool generates version {GENERATOR_VERSION}",
+                    self.generator_version
+                )
+                .into());
+            }
+
+            chunk.apply(&decode_chunk_delta(&row.data)?.value);
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// The lowest and highest stored chunk in each chunk column of a region.
+    async fn stored_columns(
+        &self,
+        min: (i32, i32),
+        max: (i32, i32),
+    ) -> Result<HashMap<(i32, i32), (i32, i32)>> {
+        let rows: Vec<(i64, i64, i64, i64)> = match self.storage {
+            StorageMode::Full => sqlx::query!(
+                "
+                SELECT x, z, MIN(y) AS \"min_y!: i64\", MAX(y) AS \"max_y!: i64\" FROM chunks
+                WHERE x BETWEEN ? AND ? AND z BETWEEN ? AND ?
+                GROUP BY x, z
+                ",
+                min.0,
+                max.0,
+                min.1,
+                max.1
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| (row.x, row.z, row.min_y, row.max_y))
+            .collect(),
+            StorageMode::Delta => sqlx::query!(
+                "
+                SELECT x, z, MIN(y) AS \"min_y!: i64\", MAX(y) AS \"max_y!: i64\"
+                FROM (SELECT x, y, z FROM chunks UNION ALL SELECT x, y, z FROM chunk_deltas)
+                WHERE x BETWEEN ? AND ? AND z BETWEEN ? AND ?
+                GROUP BY x, z
+                ",
+                min.0,
+                max.0,
+                min.1,
+                max.1
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| (row.x, row.z, row.min_y, row.max_y))
+            .collect(),
+        };
+
+        Ok(rows
+            .into_iter()
+            .map(|(x, z, min_y, max_y)| ((x as i32, z as i32), (min_y as i32, max_y as i32)))
+            .collect())
+    }
+}
+
+/// Whether every migration has been applied to a database, without applying any.
+async fn is_migrated(db: &SqlitePool) -> Result<bool> {
+    let has_migrations: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')",
+    )
+    .fetch_one(db)
+    .await?;
+
+    if !has_migrations {
+        return Ok(false);
+    }
+
+    let applied: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(db)
+            .await?;
+
+    Ok(sqlx::migrate!()
+        .iter()
+        .all(|migration| applied.contains(&migration.version)))
+}
+
+async fn print_histogram(world: &WorldFile, positions: &[ChunkPos]) -> Result<()> {
+    let mut counts: HashMap<Block, usize> = HashMap::new();
+    let mut chunks = 0;
+
+    for &pos in positions {
+        let chunk = match world.load_chunk(pos).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => {
+                eprintln!("Chunk at {}, {}, {} isn't stored", pos.x, pos.y, pos.z);
+                continue;
+            }
+            Err(error) => {
+                eprintln!("Skipping chunk at {}, {}, {}: {error}", pos.x, pos.y, pos.z);
+                continue;
+            }
+        };
+
+        for index in 0..CHUNK_INDICES {
+            *counts
+                .entry(chunk.get(LocalPos::from_index(index)))
+                .or_default() += 1;
+        }
+
+        chunks += 1;
+    }
+
+    let mut counts: Vec<(Block, usize)> = counts.into_iter().collect();
+    counts.sort_by_key(|&(_, count)| Reverse(count));
+
+    println!("{chunks} chunks");
+
+    for (block, count) in counts {
+        println!("{:<8} {count}", format!("{block:?}"));
+    }
+
+    Ok(())
+}
+
+/// Renders the highest block of every column in the region, along with its height.
+async fn render(
+    world: &WorldFile,
+    min: (i32, i32),
+    max: (i32, i32),
+    surface_path: &Path,
+    heightmap_path: &Path,
+) -> Result<()> {
+    let size = CHUNK_SIZE as i32;
+    let width = ((max.0 - min.0 + 1) * size) as u32;
+    let depth = ((max.1 - min.1 + 1) * size) as u32;
+
+    let stored = world.stored_columns(min, max).await?;
+    let mut surface: Vec<Option<(Block, i32)>> = vec![None; (width * depth) as usize];
+
+    for chunk_x in min.0..=max.0 {
+        for chunk_z in min.1..=max.1 {
+            let mut range = stored.get(&(chunk_x, chunk_z)).copied();
+
+            // Terrain that hasn't been changed isn't stored, so include what the generator produces
+            if world.storage == StorageMode::Delta {
+                let heights = (0..size).flat_map(|x| {
+                    (0..size).map(move |z| {
+                        world
+                            .generator
+                            .surface_height(chunk_x * size + x, chunk_z * size + z)
+                    })
+                });
+
+                let (low, high) = heights.fold((i32::MAX, i32::MIN), |(low, high), height| {
+                    (low.min(height), high.max(height))
+                });
+
+                let generated = (
+                    low.div_euclid(size),
+                    (high + world.generator.max_structure_height()).div_euclid(size),
+                );
+
+                range = Some(match range {
+                    Some((bottom, top)) => (bottom.min(generated.0), top.max(generated.1)),
+                    None => generated,
+                });
+            }
+
+            let Some((bottom, top)) = range else {
+                continue;
+            };
+
+            let mut remaining = CHUNK_SIZE * CHUNK_SIZE;
+
+            for chunk_y in (bottom..=top).rev() {
+                let pos = ChunkPos::new(chunk_x, chunk_y, chunk_z);
+
+                let chunk = match world.load_chunk(pos).await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => continue,
+                    Err(error) => {
+                        eprintln!("Skipping chunk at {}, {}, {}: {error}", pos.x, pos.y, pos.z);
+                        continue;
+                    }
+                };
+
+                for x in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        let pixel_x = (chunk_x - min.0) as u32 * size as u32 + x as u32;
+                        let pixel_z = (chunk_z - min.1) as u32 * size as u32 + z as u32;
+                        let column = &mut surface[(pixel_z * width + pixel_x) as usize];
+
+                        if column.is_some() {
+                            continue;
+                        }
+
+                        let highest = (0..CHUNK_SIZE)
+                            .rev()
+                            .map(|y| (y, chunk.get(LocalPos::new(x, y, z))))
+                            .find(|&(_, block)| block != Block::Air);
+
+                        if let Some((y, block)) = highest {
+                            *column = Some((block, chunk_y * size + y as i32));
+                            remaining -= 1;
+                        }
+                    }
+                }
+
+                if remaining == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    let heights = surface.iter().flatten().map(|&(_, height)| height);
+    let lowest = heights.clone().min().unwrap_or_default();
+    let highest = heights.max().unwrap_or_default();
+
+    let mut surface_image = RgbImage::new(width, depth);
+    let mut heightmap_image = GrayImage::new(width, depth);
+
+    for (index, column) in surface.iter().enumerate() {
+        let Some((block, height)) = *column else {
+            continue;
+        };
+
+        let x = index as u32 % width;
+        let z = index as u32 / width;
+
+        let shade = if highest > lowest {
+            (height - lowest) as f32 / (highest - lowest) as f32
+        } else {
+            1.0
+        };
+
+        surface_image.put_pixel(x, z, map_color(block));
+        heightmap_image.put_pixel(x, z, Luma([(32.0 + shade * 223.0) as u8]));
+    }
+
+    surface_image.save(surface_path)?;
+    heightmap_image.save(heightmap_path)?;
+
+    println!(
+        "Rendered {width}x{depth} blocks, with heights from {lowest} to {highest}, \
+        to {} and {}",
+        surface_path.display(),
+        heightmap_path.display()
+    );
+
+    Ok(())
+}
+
+fn map_color(block: Block) -> Rgb<u8> {
+    Rgb(match block {
+        Block::Air => [0, 0, 0],
+        Block::Rock => [120, 120, 120],
+        Block::Dirt => [121, 85, 58],
+        Block::Grass => [92, 160, 60],
+        Block::Leaves => [46, 110, 40],
+        Block::Wood => [102, 76, 46],
+        Block::Sand => [219, 206, 160],
+        Block::Water => [50, 90, 200],
+        Block::Gravel => [150, 140, 135],
+    })
+}
+
+/// Deletes every stored chunk whose column is further than the radius from the center column,
+/// or only counts them if it hasn't been confirmed.
+async fn prune(world: &WorldFile, radius: i32, center: (i32, i32), confirmed: bool) -> Result<()> {
+    let radius_sq = radius * radius;
+
+    if !confirmed {
+        let count = match world.storage {
+            StorageMode::Full => sqlx::query!(
+                "SELECT COUNT(*) AS count FROM chunks WHERE (x - ?1) * (x - ?1) + (z - ?2) * (z - ?2) > ?3",
+                center.0,
+                center.1,
+                radius_sq
+            )
+            .fetch_one(&world.db)
+            .await?
+            .count,
+            StorageMode::Delta => sqlx::query!(
+                "
+                SELECT
+                    (SELECT COUNT(*) FROM chunks WHERE (x - ?1) * (x - ?1) + (z - ?2) * (z - ?2) > ?3)
+                    + (SELECT COUNT(*) FROM chunk_deltas WHERE (x - ?1) * (x - ?1) + (z - ?2) * (z - ?2) > ?3)
+                    AS \"count!: i64\"
+                ",
+                center.0,
+                center.1,
+                radius_sq
+            )
+            .fetch_one(&world.db)
+            .await?
+            .count,
+        };
+
+        println!(
+            "Would delete {count} chunks further than {radius} chunks from {}, {}. \
+            Run again with --yes to delete them.",
+            center.0, center.1
+        );
+
+        return Ok(());
+    }
+
+    let mut deleted = sqlx::query!(
+        "DELETE FROM chunks WHERE (x - ?1) * (x - ?1) + (z - ?2) * (z - ?2) > ?3",
+        center.0,
+        center.1,
+        radius_sq
+    )
+    .execute(&world.db)
+    .await?
+    .rows_affected();
+
+    // Delta worlds may still have changes stored by an older version
+    if world.storage == StorageMode::Delta {
+        deleted += sqlx::query!(
+            "DELETE FROM chunk_deltas WHERE (x - ?1) * (x - ?1) + (z - ?2) * (z - ?2) > ?3",
+            center.0,
+            center.1,
+            radius_sq
+        )
+        .execute(&world.db)
+        .await?
+        .rows_affected();
+    }
+
+    println!(
+        "Deleted {deleted} chunks further than {radius} chunks from {}, {}",
+        center.0, center.1
+    );
+
+    Ok(())
+}
+
+async fn print_player(world: &WorldFile) -> Result<()> {
+    let player = sqlx::query!("SELECT x, y, z, roll, pitch, yaw, inventory FROM player")
+        .fetch_one(&world.db)
+        .await?;
+
+    println!("Position: {}, {}, {}", player.x, player.y, player.z);
+    println!(
+        "Rotation: roll {}, pitch {}, yaw {}",
+        player.roll, player.pitch, player.yaw
+    );
+
+    match player.inventory {
+        Some(data) => {
+            let inventory = decode_inventory(&data)?;
+            println!("Inventory: {:#?}", inventory.value);
+        }
+        None => println!("Inventory: default"),
+    }
+
+    Ok(())
+}
+
+async fn backup(world: &WorldFile, retention: usize) -> Result<()> {
+    let path = create_backup(&world.db, &world.backups_dir(), retention).await?;
+    println!("Backed up the world to {}", path.display());
+    Ok(())
+}
+
+/// Restores a backup, given either its path, its file name in the backups folder, or `latest`,
+/// or only shows which backup it is if it hasn't been confirmed.
+async fn restore(world: &WorldFile, backup: &str, confirmed: bool) -> Result<()> {
+    let dir = world.backups_dir();
+
+    let path = if backup == "latest" {
+        list_backups(&dir)?
+            .into_iter()
+            .next()
+            .map(|backup| backup.path)
+            .ok_or("there are no backups")?
+    } else if Path::new(backup).is_file() {
+        PathBuf::from(backup)
+    } else {
+        dir.join(backup)
+    };
+
+    if !path.is_file() {
+        return Err(format!("{} doesn't exist", path.display()).into());
+    }
+
+    if !confirmed {
+        println!(
+            "Would replace the world with {}. Run again with --yes to restore it.",
+            path.display()
+        );
+
+        return Ok(());
+    }
+
+    // Keep the world as it was, in case the wrong backup was picked. One more backup is kept
+    // than usual, so that making it never deletes the backup being restored
+    let retention = BackupSettings::default().retention.max(1) + 1;
+    let kept = create_backup(&world.db, &world.backups_dir(), retention).await?;
+    println!("Backed up the world to {}", kept.display());
+
+    restore_backup(&world.db, &path).await?;
+    println!("Restored the world from {}", path.display());
+    Ok(())
+}
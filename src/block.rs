@@ -2,35 +2,9 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::item::{Item, ItemKind, Material, ToolPart};
-use crate::level::Level;
-use crate::position::BlockPos;
-use crate::voxel_mesh::{VoxelFace, VoxelMesh};
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[allow(clippy::struct_excessive_bools)]
-pub struct BlockFaces {
-    pub left: bool,
-    pub right: bool,
-    pub front: bool,
-    pub back: bool,
-    pub top: bool,
-    pub bottom: bool,
-}
-
-impl BlockFaces {
-    pub fn get(self, face: VoxelFace) -> bool {
-        match face {
-            VoxelFace::Left => self.left,
-            VoxelFace::Right => self.right,
-            VoxelFace::Front => self.front,
-            VoxelFace::Back => self.back,
-            VoxelFace::Top => self.top,
-            VoxelFace::Bottom => self.bottom,
-        }
-    }
-}
+use crate::voxel_mesh::VoxelFace;
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Block {
     #[default]
@@ -120,43 +94,29 @@ impl Block {
         !matches!(self, Self::Air | Self::Leaves | Self::Water)
     }
 
-    pub fn render(
-        self,
-        mesh: &mut VoxelMesh,
-        level: &Level,
-        block_pos: BlockPos,
-        faces: BlockFaces,
-    ) {
-        for face in [
-            VoxelFace::Left,
-            VoxelFace::Right,
-            VoxelFace::Front,
-            VoxelFace::Back,
-            VoxelFace::Top,
-            VoxelFace::Bottom,
-        ] {
-            if faces.get(face) {
-                mesh.render_face(
-                    level,
-                    block_pos,
-                    face,
-                    #[allow(clippy::match_same_arms)]
-                    match (self, face) {
-                        (Self::Air, _) => continue,
-                        (Self::Rock, _) => 0,
-                        (Self::Dirt, _) => 1,
-                        (Self::Grass, VoxelFace::Top) => 3,
-                        (Self::Grass, VoxelFace::Bottom) => 1,
-                        (Self::Grass, _) => 2,
-                        (Self::Leaves, _) => 4,
-                        (Self::Wood, VoxelFace::Top | VoxelFace::Bottom) => 6,
-                        (Self::Wood, _) => 5,
-                        (Self::Sand, _) => 7,
-                        (Self::Water, _) => 8,
-                        (Self::Gravel, _) => 9,
-                    },
-                );
-            }
-        }
+    /// Whether trees and other plants can grow on top of the block.
+    pub fn supports_plants(self) -> bool {
+        matches!(self, Self::Grass | Self::Dirt)
+    }
+
+    /// Which texture in the block texture array a face of this block is drawn with, if any.
+    pub fn texture_index(self, face: VoxelFace) -> Option<u32> {
+        #[allow(clippy::match_same_arms)]
+        let index = match (self, face) {
+            (Self::Air, _) => return None,
+            (Self::Rock, _) => 0,
+            (Self::Dirt, _) => 1,
+            (Self::Grass, VoxelFace::Top) => 3,
+            (Self::Grass, VoxelFace::Bottom) => 1,
+            (Self::Grass, _) => 2,
+            (Self::Leaves, _) => 4,
+            (Self::Wood, VoxelFace::Top | VoxelFace::Bottom) => 6,
+            (Self::Wood, _) => 5,
+            (Self::Sand, _) => 7,
+            (Self::Water, _) => 8,
+            (Self::Gravel, _) => 9,
+        };
+
+        Some(index)
     }
 }
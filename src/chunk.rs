@@ -1,21 +1,48 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    block::{Block, BlockFaces},
-    level::Level,
-    position::{BlockPos, ChunkPos, LocalPos, CHUNK_INDICES},
-    voxel_mesh::VoxelMesh,
+    block::Block,
+    position::{BlockPos, ChunkPos, LocalPos, CHUNK_INDICES, CHUNK_SIZE},
+    voxel_mesh::{ambient_occlusion, FaceAppearance, FaceLayer, MeshingMode, VoxelFace, VoxelMesh},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
-    blocks: Vec<Block>,
+    storage: ChunkStorage,
+}
+
+/// Most chunks are made up of a single block (air above the terrain, rock below it),
+/// so those are stored as just that block. Everything else is stored as a palette of
+/// the blocks in the chunk, plus a bit-packed index into that palette for each block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ChunkStorage {
+    Single(Block),
+    Paletted(PalettedBlocks),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PalettedBlocks {
+    palette: Vec<Block>,
+    bits: u32,
+    words: Vec<u64>,
+}
+
+/// The blocks that differ between a chunk and the chunk it was generated as.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChunkDelta {
+    changes: Vec<(u16, Block)>,
+}
+
+impl ChunkDelta {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
 }
 
 impl Default for Chunk {
     fn default() -> Self {
         Self {
-            blocks: vec![Block::Air; CHUNK_INDICES],
+            storage: ChunkStorage::Single(Block::Air),
         }
     }
 }
@@ -26,37 +53,440 @@ impl Chunk {
     }
 
     pub fn get(&self, pos: LocalPos) -> Block {
-        self.blocks[pos.index()]
+        match &self.storage {
+            ChunkStorage::Single(block) => *block,
+            ChunkStorage::Paletted(blocks) => blocks.get(pos.index()),
+        }
     }
 
     pub fn set(&mut self, pos: LocalPos, block: Block) {
-        self.blocks[pos.index()] = block;
+        match &mut self.storage {
+            ChunkStorage::Single(existing) => {
+                if *existing == block {
+                    return;
+                }
+
+                let mut blocks = PalettedBlocks::filled(*existing);
+                blocks.set(pos.index(), block);
+                self.storage = ChunkStorage::Paletted(blocks);
+            }
+            ChunkStorage::Paletted(blocks) => {
+                blocks.set(pos.index(), block);
+            }
+        }
     }
 
-    pub fn render(&self, level: &Level, chunk_pos: ChunkPos) -> VoxelMesh {
-        let mut mesh = VoxelMesh::new();
+    /// Drops unused palette entries, and collapses the chunk back into a single block if possible.
+    pub fn optimize(&mut self) {
+        let ChunkStorage::Paletted(blocks) = &mut self.storage else {
+            return;
+        };
+
+        blocks.compact();
+
+        if let [block] = blocks.palette[..] {
+            self.storage = ChunkStorage::Single(block);
+        }
+    }
+
+    /// Whether the chunk is all air, so there's nothing to render.
+    pub fn is_empty(&self) -> bool {
+        matches!(self.storage, ChunkStorage::Single(Block::Air))
+    }
+
+    /// Whether every block in the chunk is solid, so the only faces it could render are on its
+    /// sides, and only where a neighboring chunk has a gap.
+    pub fn is_opaque(&self) -> bool {
+        matches!(self.storage, ChunkStorage::Single(block) if block.is_solid())
+    }
+
+    /// Whether every block on one side of the chunk is solid, hiding the faces behind it.
+    pub fn is_face_opaque(&self, face: VoxelFace) -> bool {
+        let blocks = match &self.storage {
+            ChunkStorage::Single(block) => return block.is_solid(),
+            ChunkStorage::Paletted(blocks) => blocks,
+        };
+
+        let edge = CHUNK_SIZE - 1;
+
+        (0..CHUNK_SIZE).all(|a| {
+            (0..CHUNK_SIZE).all(|b| {
+                let pos = match face {
+                    VoxelFace::Left => LocalPos::new(0, a, b),
+                    VoxelFace::Right => LocalPos::new(edge, a, b),
+                    VoxelFace::Bottom => LocalPos::new(a, 0, b),
+                    VoxelFace::Top => LocalPos::new(a, edge, b),
+                    VoxelFace::Back => LocalPos::new(a, b, 0),
+                    VoxelFace::Front => LocalPos::new(a, b, edge),
+                };
+
+                blocks.get(pos.index()).is_solid()
+            })
+        })
+    }
+
+    /// Roughly how many bytes the chunk takes up in memory.
+    pub fn memory_size(&self) -> usize {
+        let blocks = match &self.storage {
+            ChunkStorage::Single(_) => 0,
+            ChunkStorage::Paletted(blocks) => {
+                blocks.palette.capacity() * size_of::<Block>()
+                    + blocks.words.capacity() * size_of::<u64>()
+            }
+        };
+
+        size_of::<Self>() + blocks
+    }
+
+    /// Finds the blocks that have been changed, compared to the original chunk.
+    pub fn diff(&self, original: &Chunk) -> ChunkDelta {
+        if let (ChunkStorage::Single(a), ChunkStorage::Single(b)) =
+            (&self.storage, &original.storage)
+        {
+            if a == b {
+                return ChunkDelta::default();
+            }
+        }
+
+        let changes = (0..CHUNK_INDICES)
+            .filter_map(|index| {
+                let pos = LocalPos::from_index(index);
+                let block = self.get(pos);
+                (block != original.get(pos)).then_some((index as u16, block))
+            })
+            .collect();
+
+        ChunkDelta { changes }
+    }
+
+    pub fn apply(&mut self, delta: &ChunkDelta) {
+        for &(index, block) in &delta.changes {
+            if (index as usize) < CHUNK_INDICES {
+                self.set(LocalPos::from_index(index as usize), block);
+            }
+        }
+
+        self.optimize();
+    }
+}
+
+impl PalettedBlocks {
+    fn filled(block: Block) -> Self {
+        Self::with_bits(vec![block], 1)
+    }
+
+    fn with_bits(palette: Vec<Block>, bits: u32) -> Self {
+        let per_word = 64 / bits as usize;
+
+        Self {
+            palette,
+            bits,
+            words: vec![0; CHUNK_INDICES.div_ceil(per_word)],
+        }
+    }
+
+    fn get(&self, index: usize) -> Block {
+        self.palette[self.palette_index(index)]
+    }
+
+    fn set(&mut self, index: usize, block: Block) {
+        let palette_index = match self.palette.iter().position(|&entry| entry == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                if self.palette.len() == 1 << self.bits {
+                    // Try to reclaim unused entries before widening the indices
+                    self.compact();
+                }
+
+                if self.palette.len() == 1 << self.bits {
+                    self.repack(self.bits * 2);
+                }
+
+                self.palette.push(block);
+                self.palette.len() - 1
+            }
+        };
+
+        self.set_palette_index(index, palette_index);
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        let per_word = 64 / self.bits as usize;
+        let mask = (1u64 << self.bits) - 1;
+        let shift = (index % per_word) as u32 * self.bits;
+        ((self.words[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = 64 / self.bits as usize;
+        let mask = (1u64 << self.bits) - 1;
+        let shift = (index % per_word) as u32 * self.bits;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !(mask << shift)) | ((palette_index as u64 & mask) << shift);
+    }
+
+    fn repack(&mut self, bits: u32) {
+        let mut repacked = Self::with_bits(self.palette.clone(), bits);
+
+        for index in 0..CHUNK_INDICES {
+            repacked.set_palette_index(index, self.palette_index(index));
+        }
+
+        *self = repacked;
+    }
+
+    fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+
+        for index in 0..CHUNK_INDICES {
+            used[self.palette_index(index)] = true;
+        }
+
+        if used.iter().all(|&used| used) {
+            return;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+
+        for (old_index, &block) in self.palette.iter().enumerate() {
+            if used[old_index] {
+                remap[old_index] = palette.len();
+                palette.push(block);
+            }
+        }
+
+        let mut bits = 1;
+        while 1 << bits < palette.len() {
+            bits *= 2;
+        }
+
+        let mut compacted = Self::with_bits(palette, bits);
+
+        for index in 0..CHUNK_INDICES {
+            compacted.set_palette_index(index, remap[self.palette_index(index)]);
+        }
+
+        *self = compacted;
+    }
+}
+
+/// A copy of a chunk along with a one block border of its neighbors, which is everything
+/// needed to mesh it without holding on to the level.
+#[derive(Debug, Clone)]
+pub struct ChunkSnapshot {
+    chunk_pos: ChunkPos,
+    blocks: Vec<Block>,
+}
+
+impl ChunkSnapshot {
+    const SIZE: usize = CHUNK_SIZE + 2;
+
+    /// Copies the chunk at `chunk_pos` and the edges of its neighbors, using `chunk_at` to
+    /// look them up. Missing neighbors are filled in with air.
+    pub fn new<'a>(chunk_pos: ChunkPos, chunk_at: impl Fn(ChunkPos) -> Option<&'a Chunk>) -> Self {
+        let mut blocks = vec![Block::Air; Self::SIZE * Self::SIZE * Self::SIZE];
+
+        // Only the layer of each neighbor that touches the chunk is needed
+        let range = |offset: i32| match offset {
+            -1 => CHUNK_SIZE - 1..CHUNK_SIZE,
+            0 => 0..CHUNK_SIZE,
+            _ => 0..1,
+        };
 
-        for (index, block) in self.blocks.iter().enumerate() {
-            if block == &Block::Air {
-                continue;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(chunk) = chunk_at(chunk_pos + ChunkPos::new(dx, dy, dz)) else {
+                        continue;
+                    };
+
+                    for x in range(dx) {
+                        for y in range(dy) {
+                            for z in range(dz) {
+                                let index = Self::index(
+                                    (dx * CHUNK_SIZE as i32 + x as i32 + 1) as usize,
+                                    (dy * CHUNK_SIZE as i32 + y as i32 + 1) as usize,
+                                    (dz * CHUNK_SIZE as i32 + z as i32 + 1) as usize,
+                                );
+
+                                blocks[index] = chunk.get(LocalPos::new(x, y, z));
+                            }
+                        }
+                    }
+                }
             }
+        }
+
+        Self { chunk_pos, blocks }
+    }
 
-            let block_pos = LocalPos::from_index(index).block_pos(chunk_pos);
-            let faces = visible_faces(level, block_pos);
-            block.render(&mut mesh, level, block_pos, faces);
+    /// The block at a position within the chunk or its border, or air anywhere further out.
+    pub fn block(&self, pos: BlockPos) -> Block {
+        let origin = LocalPos::new(0, 0, 0).block_pos(self.chunk_pos);
+        let [x, y, z] =
+            [pos.x - origin.x, pos.y - origin.y, pos.z - origin.z].map(|offset| offset + 1);
+
+        if [x, y, z]
+            .iter()
+            .any(|&offset| !(0..Self::SIZE as i32).contains(&offset))
+        {
+            return Block::Air;
+        }
+
+        self.blocks[Self::index(x as usize, y as usize, z as usize)]
+    }
+
+    pub fn render(&self, mode: MeshingMode) -> VoxelMesh {
+        let mut mesh = VoxelMesh::new();
+
+        for face in VoxelFace::ALL {
+            let [normal, axis_a, axis_b] = face.axes();
+
+            for layer in 0..CHUNK_SIZE {
+                let mut faces: FaceLayer = [None; CHUNK_SIZE * CHUNK_SIZE];
+
+                for b in 0..CHUNK_SIZE {
+                    for a in 0..CHUNK_SIZE {
+                        let mut local = [0; 3];
+                        local[normal] = layer;
+                        local[axis_a] = a;
+                        local[axis_b] = b;
+
+                        let block_pos =
+                            LocalPos::new(local[0], local[1], local[2]).block_pos(self.chunk_pos);
+
+                        let block = self.block(block_pos);
+
+                        let Some(tex_index) = block.texture_index(face) else {
+                            continue;
+                        };
+
+                        // Faces against solid blocks can't be seen, and water is drawn as one
+                        // surface rather than a face for every block of it
+                        let neighbor = self.block(block_pos + face.normal());
+
+                        if neighbor.is_solid() || (block == Block::Water && neighbor == block) {
+                            continue;
+                        }
+
+                        faces[b * CHUNK_SIZE + a] = Some(FaceAppearance {
+                            tex_index,
+                            ao: ambient_occlusion(self, block_pos, face),
+                        });
+                    }
+                }
+
+                mesh.add_layer(face, layer as u32, &mut faces, mode);
+            }
         }
 
         mesh
     }
+
+    /// Blocks are stored with the chunk's own blocks starting at one in each direction.
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        (x * Self::SIZE + y) * Self::SIZE + z
+    }
 }
 
-fn visible_faces(level: &Level, pos: BlockPos) -> BlockFaces {
-    BlockFaces {
-        left: !level.block(pos.left()).is_solid(),
-        right: !level.block(pos.right()).is_solid(),
-        front: !level.block(pos.front()).is_solid(),
-        back: !level.block(pos.back()).is_solid(),
-        top: !level.block(pos.top()).is_solid(),
-        bottom: !level.block(pos.bottom()).is_solid(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCKS: [Block; 9] = [
+        Block::Air,
+        Block::Rock,
+        Block::Dirt,
+        Block::Grass,
+        Block::Leaves,
+        Block::Wood,
+        Block::Sand,
+        Block::Water,
+        Block::Gravel,
+    ];
+
+    fn block_at(index: usize) -> Block {
+        BLOCKS[index % BLOCKS.len()]
+    }
+
+    /// A chunk with every kind of block, spread evenly through it.
+    fn mixed_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+
+        for index in 0..CHUNK_INDICES {
+            chunk.set(LocalPos::from_index(index), block_at(index));
+        }
+
+        chunk
+    }
+
+    fn assert_same_blocks(a: &Chunk, b: &Chunk) {
+        for index in 0..CHUNK_INDICES {
+            let pos = LocalPos::from_index(index);
+            assert_eq!(a.get(pos), b.get(pos), "block {index}");
+        }
+    }
+
+    #[test]
+    fn filling_every_block_widens_indices() {
+        let chunk = mixed_chunk();
+
+        let ChunkStorage::Paletted(blocks) = &chunk.storage else {
+            panic!("a chunk with more than one block should be paletted");
+        };
+
+        assert_eq!(blocks.palette.len(), BLOCKS.len());
+        assert_eq!(blocks.bits, 4);
+
+        for index in 0..CHUNK_INDICES {
+            assert_eq!(chunk.get(LocalPos::from_index(index)), block_at(index));
+        }
+    }
+
+    #[test]
+    fn optimizing_drops_unused_blocks() {
+        let mut chunk = mixed_chunk();
+
+        for index in 1..CHUNK_INDICES {
+            chunk.set(LocalPos::from_index(index), Block::Rock);
+        }
+
+        chunk.optimize();
+
+        let ChunkStorage::Paletted(blocks) = &chunk.storage else {
+            panic!("a chunk with two blocks should stay paletted");
+        };
+
+        assert_eq!(blocks.palette, [Block::Air, Block::Rock]);
+        assert_eq!(blocks.bits, 1);
+
+        chunk.set(LocalPos::from_index(0), Block::Rock);
+        chunk.optimize();
+
+        assert!(matches!(chunk.storage, ChunkStorage::Single(Block::Rock)));
+        assert_eq!(chunk.memory_size(), size_of::<Chunk>());
+    }
+
+    #[test]
+    fn applying_a_diff_restores_the_chunk() {
+        let original = mixed_chunk();
+        let mut modified = original.clone();
+
+        for index in (0..CHUNK_INDICES).step_by(7) {
+            modified.set(LocalPos::from_index(index), Block::Wood);
+        }
+
+        let delta = modified.diff(&original);
+        assert!(!delta.is_empty());
+        assert!(original.diff(&original).is_empty());
+
+        let mut restored = original.clone();
+        restored.apply(&delta);
+        assert_same_blocks(&restored, &modified);
+
+        let mut reverted = modified.clone();
+        reverted.apply(&original.diff(&modified));
+        assert_same_blocks(&reverted, &original);
     }
 }
@@ -1,26 +1,48 @@
-mod generator;
-
-use std::time::Duration;
-
+pub mod backup;
+mod cache;
+pub mod format;
+pub mod generator;
+mod loading;
+mod meshing;
+mod persistence;
+mod render_distance;
+mod saving;
+pub mod world;
+
+use std::{path::Path, time::Duration};
+
+use backup::{start_backups, start_restore, BackupSettings, RestoreBackup};
 use bevy::{prelude::*, utils::HashMap, utils::HashSet};
 use bevy_tokio_tasks::TokioTasksRuntime;
-use generator::LevelGenerator;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use sqlx::SqlitePool;
+use cache::{evict_cached_chunks, ChunkCache};
+use generator::{preset::WorldPreset, LevelGenerator, GENERATOR_VERSION};
+use loading::{start_chunk_generation, store_changed_chunks_in_full};
+use meshing::{apply_chunk_meshes, start_chunk_meshing, ChunkMeshTasks};
+use persistence::{report, retry};
+use saving::{flush_on_exit, start_saving, SaveLock};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous},
+    SqlitePool,
+};
 use tokio::time::sleep;
+use world::StorageMode;
 
 use crate::{
     block::Block,
-    chunk::Chunk,
+    chunk::{Chunk, ChunkSnapshot},
     game_state::{is_unpaused, GameState},
-    inventory::Inventory,
-    loader::{BlockInteraction, GlobalTextureArray, VoxelMaterial},
-    player::{Player, PlayerCamera},
+    player::Player,
     position::{BlockPos, ChunkPos},
+    voxel_mesh::VoxelFace,
 };
 
-const CHUNK_UNLOAD_DISTANCE: i32 = 10; // Should be larger than generation radius
-const CHUNK_GENERATION_BATCH_SIZE: usize = 50; // Adjust this value as needed
+pub use cache::ChunkCacheSettings;
+pub use loading::{ChunkLoadingSettings, ChunkLoadingStats};
+pub use meshing::ChunkMeshSettings;
+pub use persistence::PersistenceError;
+pub use render_distance::RenderDistance;
+pub use saving::AutosaveSettings;
+pub use world::{WorldMetadata, WorldSelection};
 
 #[derive(Debug, Clone, Copy)]
 pub struct LevelPlugin;
@@ -28,26 +50,49 @@ pub struct LevelPlugin;
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Level::new())
-            .insert_resource(LevelGenerator::new(42))
             .insert_resource(ChunkGenerationQueue::default())
+            .init_resource::<ChunkCache>()
+            .init_resource::<ChunkCacheSettings>()
+            .init_resource::<RenderDistance>()
+            .init_resource::<ChunkLoadingSettings>()
+            .init_resource::<ChunkLoadingStats>()
+            .init_resource::<ChunkMeshSettings>()
+            .init_resource::<ChunkMeshTasks>()
+            .init_resource::<SaveLock>()
+            .init_resource::<AutosaveSettings>()
+            .init_resource::<BackupSettings>()
+            .add_event::<PersistenceError>()
+            .add_event::<BlockChanged>()
+            .add_event::<RestoreBackup>()
             .add_systems(OnEnter(GameState::Setup), setup_level)
             .add_systems(
                 OnEnter(GameState::Playing),
-                (start_chunk_generation, start_saving),
+                (start_chunk_generation, start_saving, start_backups),
             )
+            .add_systems(Update, start_restore.run_if(in_state(GameState::Playing)))
             .add_systems(
                 Update,
-                (build_chunk_meshes, unload_distant_chunks)
+                (
+                    start_chunk_meshing,
+                    apply_chunk_meshes,
+                    unload_distant_chunks,
+                    evict_cached_chunks,
+                )
                     .chain()
                     .run_if(in_state(GameState::Playing).and(is_unpaused)),
-            );
+            )
+            .add_systems(Last, flush_on_exit);
     }
 }
 
 #[derive(Debug, Clone)]
 struct LoadedChunk {
     chunk: Chunk,
-    entity: Entity,
+    /// Chunks only get an entity once they have something to render or need saving.
+    entity: Option<Entity>,
+    /// Whether the chunk has been meshed with all of its neighbors loaded, so that it doesn't
+    /// need meshing again when a neighbor that was unloaded in the meantime comes back.
+    meshed: bool,
 }
 
 #[derive(Resource)]
@@ -56,12 +101,17 @@ struct LevelDatabase(SqlitePool);
 #[derive(Debug, Default, Clone, Resource)]
 pub struct Level {
     chunks: HashMap<ChunkPos, LoadedChunk>,
+    /// Chunks whose generation panicked. They count as loaded for their neighbors, so that the
+    /// terrain around them is still meshed, and they're tried again once they've gone out of
+    /// range and come back.
+    panicked: HashSet<ChunkPos>,
 }
 
 impl Level {
     pub fn new() -> Self {
         Self {
             chunks: HashMap::new(),
+            panicked: HashSet::new(),
         }
     }
 
@@ -69,12 +119,24 @@ impl Level {
         self.chunks.get(&pos).map(|loaded| &loaded.chunk)
     }
 
-    pub fn chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+    /// Adds a chunk without an entity, for tests that only need its blocks.
+    #[cfg(test)]
+    pub(crate) fn insert_chunk(&mut self, pos: ChunkPos, chunk: Chunk) {
+        let loaded = LoadedChunk {
+            chunk,
+            entity: None,
+            meshed: false,
+        };
+
+        self.chunks.insert(pos, loaded);
+    }
+
+    fn chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
         self.chunks.get_mut(&pos).map(|loaded| &mut loaded.chunk)
     }
 
     pub fn entity(&self, pos: ChunkPos) -> Option<Entity> {
-        self.chunks.get(&pos).map(|loaded| loaded.entity)
+        self.chunks.get(&pos).and_then(|loaded| loaded.entity)
     }
 
     pub fn block(&self, pos: BlockPos) -> Block {
@@ -84,6 +146,147 @@ impl Level {
             .map(|chunk| chunk.get(local_pos))
             .unwrap_or(Block::Air)
     }
+
+    /// Whether a loaded chunk has any faces to render. Empty chunks don't, and neither do solid
+    /// chunks whose neighbors are solid where they meet. Sides facing chunks that haven't loaded
+    /// yet count as hidden, since the chunk is checked again once they do.
+    pub fn needs_mesh(&self, pos: ChunkPos) -> bool {
+        let Some(chunk) = self.chunk(pos) else {
+            return false;
+        };
+
+        if chunk.is_empty() {
+            return false;
+        }
+
+        if !chunk.is_opaque() {
+            return true;
+        }
+
+        [
+            (ChunkPos::NEG_X, VoxelFace::Right),
+            (ChunkPos::X, VoxelFace::Left),
+            (ChunkPos::NEG_Y, VoxelFace::Top),
+            (ChunkPos::Y, VoxelFace::Bottom),
+            (ChunkPos::NEG_Z, VoxelFace::Front),
+            (ChunkPos::Z, VoxelFace::Back),
+        ]
+        .into_iter()
+        .any(|(offset, face)| {
+            self.chunk(pos + offset)
+                .is_some_and(|neighbor| !neighbor.is_face_opaque(face))
+        })
+    }
+
+    /// Whether every chunk around a chunk is loaded, or panicked while generating. The faces and
+    /// ambient occlusion at the edges of a chunk depend on the blocks across them, so chunks
+    /// wait for this before they're meshed.
+    pub fn is_surrounded(&self, pos: ChunkPos) -> bool {
+        (-1..=1).all(|dx| {
+            (-1..=1).all(|dy| {
+                (-1..=1).all(|dz| {
+                    let neighbor = pos + ChunkPos::new(dx, dy, dz);
+                    self.chunks.contains_key(&neighbor) || self.panicked.contains(&neighbor)
+                })
+            })
+        })
+    }
+
+    /// A copy of a loaded chunk and the edges of its neighbors, for meshing it in the background.
+    pub fn snapshot(&self, pos: ChunkPos) -> Option<ChunkSnapshot> {
+        self.chunk(pos)?;
+        Some(ChunkSnapshot::new(pos, |pos| self.chunk(pos)))
+    }
+
+    /// The entity of a loaded chunk, spawning one if it doesn't have one yet.
+    pub fn spawn_entity(&mut self, commands: &mut Commands, pos: ChunkPos) -> Option<Entity> {
+        let loaded = self.chunks.get_mut(&pos)?;
+
+        let entity = *loaded.entity.get_or_insert_with(|| {
+            commands
+                .spawn((pos, Transform::from_translation(pos.world_pos())))
+                .id()
+        });
+
+        Some(entity)
+    }
+
+    /// Changes a block, marking its chunk as modified and every chunk whose mesh shows it as
+    /// dirty, and sends a [`BlockChanged`] event. Returns the block that was replaced, or
+    /// nothing if the block's chunk isn't loaded or the block was already set.
+    pub fn set_block(
+        &mut self,
+        commands: &mut Commands,
+        pos: BlockPos,
+        block: Block,
+        cause: BlockChangeCause,
+    ) -> Option<Block> {
+        let chunk_pos = pos.chunk_pos();
+        let chunk = self.chunk_mut(chunk_pos)?;
+        let old = chunk.get(pos.local_pos());
+
+        if old == block {
+            return None;
+        }
+
+        chunk.set(pos.local_pos(), block);
+
+        if let Some(entity) = self.spawn_entity(commands, chunk_pos) {
+            commands.entity(entity).insert(Modified);
+        }
+
+        // Faces and ambient occlusion look one block across, so a block on an edge or corner
+        // of its chunk shows up in the meshes of the chunks it touches as well
+        let mut affected = Vec::with_capacity(8);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let chunk_pos = (pos + BlockPos::new(dx, dy, dz)).chunk_pos();
+
+                    if !affected.contains(&chunk_pos) {
+                        affected.push(chunk_pos);
+                    }
+                }
+            }
+        }
+
+        for chunk_pos in affected {
+            self.mark_dirty(commands, chunk_pos);
+        }
+
+        commands.send_event(BlockChanged {
+            pos,
+            old,
+            new: block,
+            cause,
+        });
+
+        Some(old)
+    }
+
+    /// Marks a chunk's mesh as out of date. Chunks that had nothing to render don't have an
+    /// entity, so one is only spawned for them if they do now.
+    pub fn mark_dirty(&mut self, commands: &mut Commands, pos: ChunkPos) -> Option<Entity> {
+        if self.entity(pos).is_none() && !self.needs_mesh(pos) {
+            return None;
+        }
+
+        let entity = self.spawn_entity(commands, pos)?;
+        commands.entity(entity).insert(Dirty);
+        Some(entity)
+    }
+}
+
+/// Runs `f` with the level and a way to queue commands, then applies those commands.
+fn with_level<T>(world: &mut World, f: impl FnOnce(&mut Level, &mut Commands) -> T) -> T {
+    let result = world.resource_scope(|world, mut level: Mut<Level>| {
+        let mut commands = world.commands();
+        f(&mut level, &mut commands)
+    });
+
+    world.flush();
+    result
 }
 
 #[derive(Debug, Clone, Copy, Component)]
@@ -92,21 +295,74 @@ pub struct Dirty;
 #[derive(Debug, Clone, Copy, Component)]
 pub struct Modified;
 
+/// Sent whenever [`Level::set_block`] changes a block.
+#[derive(Debug, Clone, Copy, Event)]
+pub struct BlockChanged {
+    pub pos: BlockPos,
+    pub old: Block,
+    pub new: Block,
+    pub cause: BlockChangeCause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockChangeCause {
+    /// The player broke the block.
+    Broken,
+    /// The player placed the block.
+    Placed,
+}
+
 #[derive(Debug, Default, Resource)]
 struct ChunkGenerationQueue {
     pending: HashSet<ChunkPos>,
+    /// Bumped whenever the level is reset, so that chunks loaded before then are thrown away.
+    epoch: u64,
 }
 
-fn setup_level(runtime: ResMut<TokioTasksRuntime>) {
+fn setup_level(runtime: ResMut<TokioTasksRuntime>, selection: Res<WorldSelection>) {
+    let selection = selection.clone();
+
     runtime.spawn_background_task(|mut ctx| async move {
-        let pool = SqlitePool::connect("sqlite://./level.sqlite?mode=rwc")
-            .await
-            .unwrap();
+        let (pool, metadata) = loop {
+            match open_world(&selection).await {
+                Ok(opened) => break opened,
+                // Retrying won't change the generator, the preset or the name, so the game
+                // exits rather than waiting for a world that will never open
+                Err(
+                    error @ (PersistenceError::OutdatedGenerator { .. }
+                    | PersistenceError::CorruptPreset { .. }
+                    | PersistenceError::NameTaken { .. }),
+                ) => {
+                    error!("Couldn't open world \"{}\": {error}", selection.name);
+
+                    ctx.run_on_main_thread(|ctx| {
+                        ctx.world.send_event(AppExit::error());
+                    })
+                    .await;
+
+                    return;
+                }
+                Err(error) => {
+                    report(&mut ctx, error).await;
+                    sleep(Duration::from_secs(5)).await;
+                }
+            }
+        };
 
-        sqlx::migrate!().run(&pool).await.unwrap();
+        info!(
+            "Opened world \"{}\" with seed {} from {}",
+            metadata.name,
+            metadata.seed,
+            selection.dir().display()
+        );
 
         ctx.run_on_main_thread(move |ctx| {
             ctx.world.insert_resource(LevelDatabase(pool));
+            ctx.world.insert_resource(LevelGenerator::with_preset(
+                metadata.seed,
+                metadata.preset.clone(),
+            ));
+            ctx.world.insert_resource(metadata);
             ctx.world
                 .resource_mut::<NextState<GameState>>()
                 .set(GameState::Playing);
@@ -115,397 +371,409 @@ fn setup_level(runtime: ResMut<TokioTasksRuntime>) {
     });
 }
 
-fn start_chunk_generation(
-    texture_array: Res<GlobalTextureArray>,
-    db: Res<LevelDatabase>,
-    runtime: Res<TokioTasksRuntime>,
-    generator: Res<LevelGenerator>,
-) {
-    let texture_array = texture_array.clone();
-    let db = db.0.clone();
-    let mut generator = generator.clone();
+async fn open_world(
+    selection: &WorldSelection,
+) -> Result<(SqlitePool, WorldMetadata), PersistenceError> {
+    let dir = selection.dir();
+
+    let imported =
+        world::import_legacy_level(Path::new(".")).map_err(|error| PersistenceError::Database {
+            action: format!("import {} into the default world", world::LEVEL_FILE),
+            error: error.to_string(),
+        })?;
+
+    if let Some(path) = imported {
+        info!(
+            "Moved the level from an older version to {}",
+            path.display()
+        );
+    }
 
-    runtime.spawn_background_task(|mut ctx| async move {
-        loop {
-            let chunk_positions = ctx
-                .run_on_main_thread(|ctx| {
-                    let mut player_query = ctx.world.query_filtered::<&Transform, With<Player>>();
-                    let level = ctx.world.resource::<Level>();
-                    let queue = ctx.world.resource::<ChunkGenerationQueue>();
-
-                    let player_transform = match player_query.iter(ctx.world).next() {
-                        Some(transform) => transform,
-                        None => return Vec::new(),
-                    };
-
-                    let player_pos = player_transform.translation;
-                    let player_chunk = BlockPos::from_world(player_pos).chunk_pos();
-
-                    // Find closest non-generated chunks within radius
-                    let radius = 8;
-
-                    let mut chunks = Vec::new();
-
-                    for x in -radius..=radius {
-                        for y in -radius..=radius {
-                            for z in -radius..=radius {
-                                let pos = ChunkPos::new(
-                                    player_chunk.x + x,
-                                    player_chunk.y + y,
-                                    player_chunk.z + z,
-                                );
-
-                                // Calculate actual world-space distance from player
-                                let dx = (pos.x as f32 * 16.0) - player_pos.x;
-                                let dy = (pos.y as f32 * 16.0) - player_pos.y;
-                                let dz = (pos.z as f32 * 16.0) - player_pos.z;
-                                let distance_sq = (dx * dx + dy * dy + dz * dz) as i32;
-
-                                if distance_sq > radius * radius * 256 {
-                                    // Adjust radius for chunk size
-                                    continue;
-                                }
-
-                                if level.chunk(pos).is_some() || queue.pending.contains(&pos) {
-                                    continue;
-                                }
-
-                                chunks.push((distance_sq, pos));
-                            }
-                        }
-                    }
+    std::fs::create_dir_all(&dir).map_err(|error| PersistenceError::Database {
+        action: format!("create world directory {}", dir.display()),
+        error: error.to_string(),
+    })?;
 
-                    // Sort by distance and take the closest N chunks
-                    chunks.sort_by_key(|&(dist, _)| dist);
-                    chunks.truncate(CHUNK_GENERATION_BATCH_SIZE);
-                    chunks.into_iter().map(|(_, pos)| pos).collect()
-                })
-                .await;
+    let options = SqliteConnectOptions::new()
+        .filename(dir.join(world::LEVEL_FILE))
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal);
+
+    let pool = retry("open the level database", || {
+        SqlitePool::connect_with(options.clone())
+    })
+    .await?;
+
+    retry("migrate the level database", || sqlx::migrate!().run(&pool)).await?;
+
+    let now = world::unix_time();
+    let metadata = load_metadata(&pool, selection, now).await?;
 
-            if chunk_positions.is_empty() {
-                sleep(Duration::from_millis(500)).await;
-                continue;
+    if metadata.storage == StorageMode::Delta {
+        store_changed_chunks_in_full(&pool, &metadata).await?;
+    }
+
+    retry("update last played time", || {
+        sqlx::query!("UPDATE metadata SET last_played_at = ?", now).execute(&pool)
+    })
+    .await?;
+
+    Ok((pool, metadata))
+}
+
+/// Loads the world's metadata, or stores it if the world is new.
+async fn load_metadata(
+    pool: &SqlitePool,
+    selection: &WorldSelection,
+    now: i64,
+) -> Result<WorldMetadata, PersistenceError> {
+    let row = retry("load world metadata", || {
+        sqlx::query!(
+            "SELECT seed, name, created_at, generator_version, storage_mode, preset FROM metadata"
+        )
+        .fetch_optional(pool)
+    })
+    .await?;
+
+    let metadata = match row {
+        Some(row) if row.name != selection.name => {
+            return Err(PersistenceError::NameTaken {
+                requested: selection.name.clone(),
+                stored: row.name,
+            });
+        }
+        Some(row) => {
+            let preset = match &row.preset {
+                Some(preset) => WorldPreset::from_ron(preset),
+                None => Ok(WorldPreset::default()),
+            };
+
+            let metadata = WorldMetadata {
+                seed: row.seed as u32,
+                name: row.name,
+                created_at: row.created_at,
+                last_played_at: now,
+                generator_version: row.generator_version as u32,
+                storage: row
+                    .storage_mode
+                    .parse()
+                    .map_err(|error| PersistenceError::Database {
+                        action: "load world metadata".to_string(),
+                        error,
+                    })?,
+                preset: preset.map_err(|error| PersistenceError::CorruptPreset { error })?,
+            };
+
+            // Worlds from before presets were stored were generated with the default one, which
+            // is stored now so that they keep it even if the default changes
+            if row.preset.is_none() {
+                let preset = metadata.preset.to_ron();
+
+                retry("store world preset", || {
+                    sqlx::query!("UPDATE metadata SET preset = ?", preset).execute(pool)
+                })
+                .await?;
             }
 
-            // Mark chunks as pending
-            let chunk_positions_2 = chunk_positions.clone();
-            ctx.run_on_main_thread(move |ctx| {
-                let mut queue = ctx.world.resource_mut::<ChunkGenerationQueue>();
-                for &pos in &chunk_positions_2 {
-                    queue.pending.insert(pos);
-                }
+            metadata
+        }
+        None => {
+            // Worlds from before metadata existed were all generated with the same seed
+            let existing_chunks = retry("count existing chunks", || {
+                sqlx::query!("SELECT COUNT(*) AS count FROM chunks").fetch_one(pool)
             })
-            .await;
-
-            // Generate chunks in parallel
-            let mut chunks = Vec::new();
-            for &chunk_pos in &chunk_positions {
-                // Generate the chunk
-                let chunk = match sqlx::query!(
-                    "SELECT data FROM chunks WHERE x = ? AND y = ? AND z = ?",
-                    chunk_pos.x,
-                    chunk_pos.y,
-                    chunk_pos.z
+            .await?
+            .count;
+
+            let (seed, generator_version, storage, preset) = if existing_chunks > 0 {
+                (
+                    world::LEGACY_SEED,
+                    world::LEGACY_GENERATOR_VERSION,
+                    StorageMode::Full,
+                    WorldPreset::default(),
                 )
-                .fetch_optional(&db)
-                .await
-                .unwrap()
-                {
-                    Some(row) => bincode::deserialize(&row.data).unwrap(),
-                    None => {
-                        let chunk = generator.generate_chunk(chunk_pos);
-                        let data = bincode::serialize(&chunk).unwrap();
-                        sqlx::query!(
-                            "INSERT INTO chunks (x, y, z, data) VALUES (?, ?, ?, ?)",
-                            chunk_pos.x,
-                            chunk_pos.y,
-                            chunk_pos.z,
-                            data
-                        )
-                        .execute(&db)
-                        .await
-                        .unwrap();
-                        chunk
-                    }
-                };
-                chunks.push((chunk_pos, chunk));
-            }
+            } else {
+                (
+                    selection.seed.unwrap_or_else(rand::random),
+                    GENERATOR_VERSION,
+                    selection.storage.unwrap_or_default(),
+                    selection.preset.clone().unwrap_or_default(),
+                )
+            };
+
+            let metadata = WorldMetadata {
+                seed,
+                name: selection.name.clone(),
+                created_at: now,
+                last_played_at: now,
+                generator_version,
+                storage,
+                preset,
+            };
+
+            let storage_mode = metadata.storage.as_str();
+            let preset = metadata.preset.to_ron();
+
+            retry("store world metadata", || {
+                sqlx::query!(
+                    "
+                    INSERT INTO metadata (
+                        seed, name, created_at, last_played_at, generator_version, storage_mode,
+                        preset
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    ",
+                    metadata.seed,
+                    metadata.name,
+                    metadata.created_at,
+                    metadata.last_played_at,
+                    metadata.generator_version,
+                    storage_mode,
+                    preset
+                )
+                .execute(pool)
+            })
+            .await?;
 
-            let texture_array = texture_array.clone();
-
-            // Add the chunks to the world
-            ctx.run_on_main_thread(move |ctx| {
-                for (chunk_pos, chunk) in chunks {
-                    let material =
-                        ctx.world
-                            .resource_mut::<Assets<VoxelMaterial>>()
-                            .add(VoxelMaterial {
-                                array_texture: texture_array.textures.clone(),
-                                destroy_texture: texture_array.destroy.clone(),
-                                block_interaction: BlockInteraction::default(),
-                            });
-
-                    let entity = ctx
-                        .world
-                        .spawn((
-                            chunk_pos,
-                            Dirty,
-                            MeshMaterial3d(material),
-                            Transform::from_xyz(
-                                chunk_pos.x as f32 * 16.0,
-                                chunk_pos.y as f32 * 16.0,
-                                chunk_pos.z as f32 * 16.0,
-                            ),
-                        ))
-                        .id();
-
-                    // Get all neighboring chunk entities in one pass
-                    let mut neighbor_entities = Vec::new();
-                    {
-                        let level = ctx.world.resource::<Level>();
-                        for dx in -1..=1 {
-                            for dy in -1..=1 {
-                                for dz in -1..=1 {
-                                    if dx == 0 && dy == 0 && dz == 0 {
-                                        continue;
-                                    }
-
-                                    let neighbor_pos = ChunkPos::new(
-                                        chunk_pos.x + dx,
-                                        chunk_pos.y + dy,
-                                        chunk_pos.z + dz,
-                                    );
-
-                                    if let Some(neighbor) = level.entity(neighbor_pos) {
-                                        neighbor_entities.push(neighbor);
-                                    }
-                                }
-                            }
-                        }
-                    }
+            metadata
+        }
+    };
 
-                    // Insert the new chunk
-                    ctx.world
-                        .resource_mut::<Level>()
-                        .chunks
-                        .insert(chunk_pos, LoadedChunk { chunk, entity });
+    Ok(metadata)
+}
 
-                    // Mark all neighbors as dirty in a single pass
-                    for &neighbor in &neighbor_entities {
-                        ctx.world.entity_mut(neighbor).insert(Dirty);
-                    }
+fn unload_distant_chunks(
+    mut commands: Commands,
+    mut level: ResMut<Level>,
+    mut cache: ResMut<ChunkCache>,
+    render_distance: Res<RenderDistance>,
+    player_query: Query<&Transform, With<Player>>,
+    modified_query: Query<Has<Modified>>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
 
-                    ctx.world
-                        .resource_mut::<ChunkGenerationQueue>()
-                        .pending
-                        .remove(&chunk_pos);
-                }
-            })
-            .await;
+    let player_pos = player_transform.translation;
+    let player_chunk = BlockPos::from_world(player_pos).chunk_pos();
+
+    // Collect chunks to unload
+    let chunks_to_unload: Vec<_> = level
+        .chunks
+        .iter()
+        .filter(|(&chunk_pos, _)| render_distance.should_unload(player_chunk, chunk_pos))
+        .map(|(&pos, loaded)| (pos, loaded.entity))
+        .collect();
+
+    level
+        .panicked
+        .retain(|&pos| !render_distance.should_unload(player_chunk, pos));
+
+    // Unload chunks
+    for (chunk_pos, entity) in chunks_to_unload {
+        if let Some(loaded_chunk) = level.chunks.remove(&chunk_pos) {
+            // Keep the chunk in case it is loaded again, and hand unsaved chunks to the next save
+            let modified = entity
+                .and_then(|entity| modified_query.get(entity).ok())
+                .unwrap_or_default();
+
+            let dirty = modified || cache.is_in_flight(chunk_pos);
+            cache.insert(chunk_pos, loaded_chunk.chunk, dirty);
 
-            sleep(Duration::from_millis(100)).await;
+            // Despawn the chunk entity
+            if let Some(entity) = entity {
+                commands.entity(entity).despawn_recursive();
+            }
         }
-    });
+    }
 }
 
-fn start_saving(db: Res<LevelDatabase>, runtime: Res<TokioTasksRuntime>) {
-    let db = db.0.clone();
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use crate::position::LocalPos;
+
+    use super::*;
 
-    runtime.spawn_background_task(move |mut ctx| async move {
-        let player = sqlx::query!("SELECT x, y, z, roll, pitch, yaw, inventory FROM player")
-            .fetch_one(&db)
+    /// A migrated level database in memory. Every connection to an in-memory database gets
+    /// its own, so the pool only has one.
+    async fn memory_database() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
             .await
             .unwrap();
 
-        let player_pos = Vec3::new(player.x as f32, player.y as f32, player.z as f32);
-        let player_rotation = Quat::from_euler(
-            EulerRot::XYZ,
-            player.roll as f32,
-            player.pitch as f32,
-            player.yaw as f32,
-        );
+        sqlx::migrate!().run(&pool).await.unwrap();
+        pool
+    }
 
-        let inventory = if let Some(inventory) = player.inventory {
-            bincode::deserialize(&inventory).unwrap()
+    /// A delta world with a change to one block stored the way older versions stored it,
+    /// along with the chunk as the player left it.
+    async fn delta_world_with_stored_change(pool: &SqlitePool) -> (WorldMetadata, Chunk) {
+        let selection = WorldSelection {
+            storage: Some(StorageMode::Delta),
+            ..default()
+        };
+
+        let metadata = load_metadata(pool, &selection, 0).await.unwrap();
+        let pos = ChunkPos::new(0, 0, 0);
+
+        let generated =
+            LevelGenerator::with_preset(metadata.seed, metadata.preset.clone()).generate_chunk(pos);
+        let mut changed = generated.clone();
+        let local = LocalPos::new(1, 2, 3);
+        let block = if changed.get(local) == Block::Air {
+            Block::Rock
         } else {
-            Inventory::default()
+            Block::Air
         };
+        changed.set(local, block);
 
-        ctx.run_on_main_thread(move |ctx| {
-            ctx.world
-                .query_filtered::<&mut Transform, With<Player>>()
-                .single_mut(ctx.world)
-                .translation = player_pos;
+        sqlx::query("INSERT INTO chunk_deltas (x, y, z, data) VALUES (0, 0, 0, ?)")
+            .bind(format::encode_chunk_delta(&changed.diff(&generated)))
+            .execute(pool)
+            .await
+            .unwrap();
 
-            ctx.world
-                .query_filtered::<&mut Transform, With<PlayerCamera>>()
-                .single_mut(ctx.world)
-                .rotation = player_rotation;
+        (metadata, changed)
+    }
 
-            ctx.world.insert_resource(inventory);
-        })
-        .await;
+    #[tokio::test]
+    async fn changed_chunks_are_stored_in_full() {
+        let pool = memory_database().await;
+        let (metadata, changed) = delta_world_with_stored_change(&pool).await;
 
-        loop {
-            let (chunks_to_save, player_pos, player_rotation, inventory) = ctx
-                .run_on_main_thread(|ctx| {
-                    let modified: Vec<(Entity, ChunkPos)> = ctx
-                        .world
-                        .query_filtered::<(Entity, &ChunkPos), With<Modified>>()
-                        .iter(ctx.world)
-                        .map(|(entity, chunk_pos)| (entity, *chunk_pos))
-                        .collect();
-
-                    for (entity, _pos) in &modified {
-                        ctx.world.entity_mut(*entity).remove::<Modified>();
-                    }
+        store_changed_chunks_in_full(&pool, &metadata)
+            .await
+            .unwrap();
 
-                    let level = ctx.world.resource::<Level>();
+        let (data, deltas): (Vec<u8>, i64) = sqlx::query_as(
+            "SELECT data, (SELECT COUNT(*) FROM chunk_deltas) FROM chunks WHERE x = 0 AND y = 0 AND z = 0",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(deltas, 0);
+        assert!(format::decode_chunk(&data)
+            .unwrap()
+            .value
+            .diff(&changed)
+            .is_empty());
+
+        // Now that nothing depends on the old terrain, a new generator only changes what the
+        // player hasn't touched
+        sqlx::query("UPDATE metadata SET generator_version = ?")
+            .bind(GENERATOR_VERSION - 1)
+            .execute(&pool)
+            .await
+            .unwrap();
 
-                    let chunks: Vec<(ChunkPos, Chunk)> = modified
-                        .iter()
-                        .filter_map(|(_, pos)| Some((*pos, level.chunk(*pos)?.clone())))
-                        .collect();
+        let selection = WorldSelection {
+            storage: Some(StorageMode::Delta),
+            ..default()
+        };
 
-                    let player_pos = ctx
-                        .world
-                        .query_filtered::<&Transform, With<Player>>()
-                        .single(ctx.world)
-                        .translation;
+        let reopened = load_metadata(&pool, &selection, 1).await.unwrap();
+        store_changed_chunks_in_full(&pool, &reopened)
+            .await
+            .unwrap();
+    }
 
-                    let player_rotation = ctx
-                        .world
-                        .query_filtered::<&Transform, With<PlayerCamera>>()
-                        .single(ctx.world)
-                        .rotation
-                        .to_euler(EulerRot::XYZ);
+    #[tokio::test]
+    async fn changes_from_other_generators_are_refused() {
+        let pool = memory_database().await;
+        let (metadata, _) = delta_world_with_stored_change(&pool).await;
 
-                    let inventory = ctx.world.resource::<Inventory>();
+        // The changes were made to terrain from an older generator
+        let old_version = GENERATOR_VERSION - 1;
+        let metadata = WorldMetadata {
+            generator_version: old_version,
+            ..metadata
+        };
 
-                    (chunks, player_pos, player_rotation, inventory.clone())
-                })
-                .await;
+        let opened = store_changed_chunks_in_full(&pool, &metadata).await;
 
-            for (chunk_pos, chunk) in chunks_to_save {
-                let data = bincode::serialize(&chunk).unwrap();
+        assert!(matches!(
+            opened,
+            Err(PersistenceError::OutdatedGenerator { world, current })
+                if world == old_version && current == GENERATOR_VERSION
+        ));
 
-                sqlx::query!(
-                    "UPDATE chunks SET data = ? WHERE x = ? AND y = ? AND z = ?",
-                    data,
-                    chunk_pos.x,
-                    chunk_pos.y,
-                    chunk_pos.z
-                )
-                .execute(&db)
-                .await
-                .unwrap();
-            }
+        // Nothing was regenerated or written over
+        let (chunks, deltas): (i64, i64) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM chunks), (SELECT COUNT(*) FROM chunk_deltas)",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
 
-            let inventory = bincode::serialize(&inventory).unwrap();
-
-            sqlx::query!(
-                "
-                UPDATE player SET
-                    x = ?, y = ?, z = ?,
-                    roll = ?, pitch = ?, yaw = ?,
-                    inventory = ?
-                ",
-                player_pos.x,
-                player_pos.y,
-                player_pos.z,
-                player_rotation.0,
-                player_rotation.1,
-                player_rotation.2,
-                inventory
-            )
-            .execute(&db)
-            .await
-            .unwrap();
+        assert_eq!((chunks, deltas), (0, 1));
+    }
 
-            sleep(Duration::from_millis(500)).await;
-        }
-    });
-}
+    #[tokio::test]
+    async fn worlds_sharing_a_folder_are_refused() {
+        let pool = memory_database().await;
 
-fn build_chunk_meshes(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    level: Res<Level>,
-    dirty_query: Query<(Entity, &ChunkPos), With<Dirty>>,
-) {
-    let items = dirty_query.iter().collect::<Vec<_>>();
-
-    let items = items
-        .into_par_iter()
-        .map(|(entity, &chunk_pos)| {
-            let chunk = level.chunk(chunk_pos)?;
-            let mesh = chunk.render(&level, chunk_pos).build();
-            Some((entity, mesh))
-        })
-        .collect::<Vec<_>>();
+        let selection = WorldSelection {
+            name: "My World".to_string(),
+            ..default()
+        };
 
-    for item in items {
-        let Some((entity, mesh)) = item else {
-            continue;
+        load_metadata(&pool, &selection, 0).await.unwrap();
+        load_metadata(&pool, &selection, 1).await.unwrap();
+
+        let other = WorldSelection {
+            name: "my-world".to_string(),
+            ..default()
         };
 
-        commands
-            .entity(entity)
-            .insert(Mesh3d(meshes.add(mesh)))
-            .remove::<Dirty>();
+        assert!(matches!(
+            load_metadata(&pool, &other, 2).await,
+            Err(PersistenceError::NameTaken { requested, stored })
+                if requested == "my-world" && stored == "My World"
+        ));
     }
-}
 
-fn unload_distant_chunks(
-    mut commands: Commands,
-    mut level: ResMut<Level>,
-    player_query: Query<&Transform, With<Player>>,
-    runtime: Res<TokioTasksRuntime>,
-    db: Res<LevelDatabase>,
-) {
-    let Ok(player_transform) = player_query.get_single() else {
-        return;
-    };
+    #[tokio::test]
+    async fn corrupt_presets_are_refused() {
+        let pool = memory_database().await;
 
-    let player_pos = player_transform.translation;
-    let player_chunk = BlockPos::from_world(player_pos).chunk_pos();
-    let unload_distance_sq = CHUNK_UNLOAD_DISTANCE * CHUNK_UNLOAD_DISTANCE;
+        load_metadata(&pool, &WorldSelection::default(), 0)
+            .await
+            .unwrap();
 
-    // Collect chunks to unload
-    let chunks_to_unload: Vec<_> = level
-        .chunks
-        .iter()
-        .filter(|(&chunk_pos, _)| {
-            let dx = chunk_pos.x - player_chunk.x;
-            let dy = chunk_pos.y - player_chunk.y;
-            let dz = chunk_pos.z - player_chunk.z;
-            dx * dx + dy * dy + dz * dz > unload_distance_sq
-        })
-        .map(|(&pos, loaded)| (pos, loaded.entity))
-        .collect();
+        sqlx::query("UPDATE metadata SET preset = '(sea_level: '")
+            .execute(&pool)
+            .await
+            .unwrap();
 
-    // Unload chunks
-    for (chunk_pos, entity) in chunks_to_unload {
-        if let Some(loaded_chunk) = level.chunks.remove(&chunk_pos) {
-            // Save chunk data before unloading
-            let data = bincode::serialize(&loaded_chunk.chunk).unwrap();
-            let db = db.0.clone();
+        let opened = load_metadata(&pool, &WorldSelection::default(), 1).await;
+        assert!(matches!(
+            opened,
+            Err(PersistenceError::CorruptPreset { .. })
+        ));
+    }
 
-            runtime.spawn_background_task(move |_ctx| async move {
-                sqlx::query!(
-                    "UPDATE chunks SET data = ? WHERE x = ? AND y = ? AND z = ?",
-                    data,
-                    chunk_pos.x,
-                    chunk_pos.y,
-                    chunk_pos.z
-                )
-                .execute(&db)
-                .await
-                .unwrap();
-            });
+    #[tokio::test]
+    async fn worlds_from_before_metadata_have_no_generator_version() {
+        let pool = memory_database().await;
 
-            // Despawn the chunk entity
-            commands.entity(entity).despawn_recursive();
-        }
+        sqlx::query("INSERT INTO chunks (x, y, z, data) VALUES (0, 0, 0, ?)")
+            .bind(format::encode_chunk(&Chunk::new()))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let metadata = load_metadata(&pool, &WorldSelection::default(), 0)
+            .await
+            .unwrap();
+
+        assert_eq!(metadata.seed, world::LEGACY_SEED);
+        assert_eq!(metadata.generator_version, world::LEGACY_GENERATOR_VERSION);
+        assert_eq!(metadata.storage, StorageMode::Full);
     }
 }
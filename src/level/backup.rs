@@ -0,0 +1,453 @@
+use std::{
+    cmp::Reverse,
+    ffi::CStr,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_tokio_tasks::TokioTasksRuntime;
+use libsqlite3_sys as ffi;
+use sqlx::{
+    pool::PoolConnection,
+    sqlite::{LockedSqliteHandle, SqliteConnectOptions},
+    Connection, Sqlite, SqliteConnection, SqlitePool,
+};
+use tokio::{runtime::Handle, sync::Mutex, task, time::sleep};
+
+use super::{
+    cache::ChunkCache,
+    persistence::{report, retry},
+    saving::{load_player, SaveLock},
+    world::unix_time,
+    ChunkGenerationQueue, Level, LevelDatabase, WorldSelection,
+};
+
+pub const BACKUPS_DIR: &str = "backups";
+
+const BACKUP_PREFIX: &str = "level-";
+const BACKUP_EXTENSION: &str = "sqlite";
+const MAX_BUSY_ATTEMPTS: u32 = 50;
+const BUSY_WAIT: Duration = Duration::from_millis(20);
+
+/// Held while a backup is being made, so that two backups never pick the same name.
+static BACKUP_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct BackupSettings {
+    pub interval: Duration,
+    /// How many backups to keep, deleting the oldest ones first. At least one is always kept.
+    pub retention: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10 * 60),
+            retention: 10,
+        }
+    }
+}
+
+/// Sent to replace the open world with one of its backups.
+#[derive(Debug, Clone, Event)]
+pub struct RestoreBackup {
+    pub path: PathBuf,
+}
+
+/// Present while a backup is being restored.
+#[derive(Debug, Clone, Copy, Resource)]
+pub(super) struct Restoring;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created_at: i64,
+    /// Tells apart backups made within the same second, counting up from 0.
+    pub sequence: u32,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    Database(sqlx::Error),
+    Sqlite(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Database(error) => write!(f, "{error}"),
+            Self::Sqlite(message) => write!(f, "{message}"),
+            Self::Corrupt(message) => write!(f, "backup is corrupt ({message})"),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<io::Error> for BackupError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+/// The folder a world's backups are kept in, next to its database.
+pub fn backups_dir(world_dir: &Path) -> PathBuf {
+    world_dir.join(BACKUPS_DIR)
+}
+
+/// Lists the backups in a folder, newest first.
+pub fn list_backups(dir: &Path) -> io::Result<Vec<Backup>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let mut backups = Vec::new();
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.extension().and_then(|extension| extension.to_str()) != Some(BACKUP_EXTENSION) {
+            continue;
+        }
+
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(BACKUP_PREFIX))
+            .and_then(parse_backup_name);
+
+        if let Some((created_at, sequence)) = name {
+            backups.push(Backup {
+                path,
+                created_at,
+                sequence,
+            });
+        }
+    }
+
+    backups.sort_by_key(|backup| Reverse((backup.created_at, backup.sequence)));
+    Ok(backups)
+}
+
+/// The file name of a backup, without its extension. The sequence number is left out for the
+/// first backup in a second, which keeps the names of older backups readable.
+fn backup_name(created_at: i64, sequence: u32) -> String {
+    if sequence == 0 {
+        format!("{BACKUP_PREFIX}{created_at}")
+    } else {
+        format!("{BACKUP_PREFIX}{created_at}-{sequence}")
+    }
+}
+
+fn parse_backup_name(name: &str) -> Option<(i64, u32)> {
+    match name.split_once('-') {
+        Some((time, sequence)) => Some((time.parse().ok()?, sequence.parse().ok()?)),
+        None => Some((name.parse().ok()?, 0)),
+    }
+}
+
+/// Copies the database into a new backup while it's still in use, then deletes the oldest
+/// backups beyond the retention count. The copy is only given its final name once it's
+/// complete, so an interrupted backup is never mistaken for a real one.
+pub async fn create_backup(
+    db: &SqlitePool,
+    dir: &Path,
+    retention: usize,
+) -> Result<PathBuf, BackupError> {
+    let _guard = BACKUP_LOCK.lock().await;
+
+    fs::create_dir_all(dir)?;
+
+    let created_at = unix_time();
+    let path = (0..)
+        .map(|sequence| {
+            dir.join(format!(
+                "{}.{BACKUP_EXTENSION}",
+                backup_name(created_at, sequence)
+            ))
+        })
+        .find(|path| !path.exists())
+        .expect("there should be a free backup name");
+    let partial = path.with_extension("partial");
+
+    if partial.exists() {
+        fs::remove_file(&partial)?;
+    }
+
+    let options = SqliteConnectOptions::new()
+        .filename(&partial)
+        .create_if_missing(true);
+
+    let destination = SqliteConnection::connect_with(&options).await?;
+    let source = db.acquire().await?;
+    let (_, mut destination) =
+        copy_database_in_background(source, destination, CopyDirection::ToFile).await?;
+
+    // The copy keeps the write-ahead log mode of the original, but a backup should be a single file
+    sqlx::query("PRAGMA journal_mode = DELETE")
+        .execute(&mut destination)
+        .await?;
+
+    destination.close().await?;
+    fs::rename(&partial, &path)?;
+
+    for backup in list_backups(dir)?.iter().skip(retention.max(1)) {
+        fs::remove_file(&backup.path)?;
+    }
+
+    Ok(path)
+}
+
+/// Replaces the contents of the database with a backup, after making sure it isn't corrupt.
+/// Nothing else should write to the database while this runs.
+pub async fn restore_backup(db: &SqlitePool, backup: &Path) -> Result<(), BackupError> {
+    let options = SqliteConnectOptions::new().filename(backup).read_only(true);
+    let mut source = SqliteConnection::connect_with(&options).await?;
+
+    let check: String = sqlx::query_scalar("PRAGMA quick_check")
+        .fetch_one(&mut source)
+        .await?;
+
+    if check != "ok" {
+        return Err(BackupError::Corrupt(check));
+    }
+
+    let destination = db.acquire().await?;
+    let (destination, source) =
+        copy_database_in_background(destination, source, CopyDirection::FromFile).await?;
+
+    source.close().await?;
+    drop(destination);
+
+    // Backups from older versions of the game need to be brought up to date
+    sqlx::migrate!().run(db).await.map_err(sqlx::Error::from)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CopyDirection {
+    ToFile,
+    FromFile,
+}
+
+/// Runs [`copy_database`] between a pooled connection and a file on the blocking thread pool,
+/// since it reads and writes every page and sleeps while the source is locked. The connections
+/// are handed back once it's done.
+async fn copy_database_in_background(
+    mut pooled: PoolConnection<Sqlite>,
+    mut file: SqliteConnection,
+    direction: CopyDirection,
+) -> Result<(PoolConnection<Sqlite>, SqliteConnection), BackupError> {
+    task::spawn_blocking(move || {
+        Handle::current().block_on(async {
+            let mut pooled = pooled.lock_handle().await?;
+            let mut file = file.lock_handle().await?;
+
+            match direction {
+                CopyDirection::ToFile => copy_database(&mut pooled, &mut file),
+                CopyDirection::FromFile => copy_database(&mut file, &mut pooled),
+            }
+        })?;
+
+        Ok((pooled, file))
+    })
+    .await
+    .map_err(|error| BackupError::Io(io::Error::other(error)))?
+}
+
+/// Copies every page of one database into another with SQLite's online backup API,
+/// which gives a consistent snapshot even if other connections are writing to the source.
+fn copy_database(
+    source: &mut LockedSqliteHandle<'_>,
+    destination: &mut LockedSqliteHandle<'_>,
+) -> Result<(), BackupError> {
+    let source = source.as_raw_handle().as_ptr();
+    let destination = destination.as_raw_handle().as_ptr();
+
+    // SAFETY: Both handles are open connections, and they're locked so that nothing else
+    // uses them until the backup has finished.
+    unsafe {
+        let backup =
+            ffi::sqlite3_backup_init(destination, c"main".as_ptr(), source, c"main".as_ptr());
+
+        if backup.is_null() {
+            return Err(sqlite_error(destination));
+        }
+
+        let mut attempts = 0;
+
+        loop {
+            match ffi::sqlite3_backup_step(backup, -1) {
+                ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if attempts < MAX_BUSY_ATTEMPTS => {
+                    attempts += 1;
+                    thread::sleep(BUSY_WAIT);
+                }
+                _ => break,
+            }
+        }
+
+        // Finishing reports the error from the last step, if there was one
+        if ffi::sqlite3_backup_finish(backup) != ffi::SQLITE_OK {
+            return Err(sqlite_error(destination));
+        }
+    }
+
+    Ok(())
+}
+
+/// # Safety
+///
+/// `db` must be an open connection.
+unsafe fn sqlite_error(db: *mut ffi::sqlite3) -> BackupError {
+    let message = CStr::from_ptr(ffi::sqlite3_errmsg(db));
+    BackupError::Sqlite(message.to_string_lossy().into_owned())
+}
+
+pub(super) fn start_backups(
+    db: Res<LevelDatabase>,
+    selection: Res<WorldSelection>,
+    runtime: Res<TokioTasksRuntime>,
+) {
+    let db = db.0.clone();
+    let dir = backups_dir(&selection.dir());
+
+    runtime.spawn_background_task(move |mut ctx| async move {
+        loop {
+            let settings = ctx
+                .run_on_main_thread(|ctx| *ctx.world.resource::<BackupSettings>())
+                .await;
+
+            match retry("back up the world", || {
+                create_backup(&db, &dir, settings.retention)
+            })
+            .await
+            {
+                Ok(path) => info!("Backed up the world to {}", path.display()),
+                Err(error) => report(&mut ctx, error).await,
+            }
+
+            sleep(settings.interval).await;
+        }
+    });
+}
+
+pub(super) fn start_restore(
+    mut commands: Commands,
+    mut events: EventReader<RestoreBackup>,
+    restoring: Option<Res<Restoring>>,
+    db: Res<LevelDatabase>,
+    selection: Res<WorldSelection>,
+    settings: Res<BackupSettings>,
+    save_lock: Res<SaveLock>,
+    runtime: Res<TokioTasksRuntime>,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+
+    if restoring.is_some() {
+        warn!("Already restoring a backup");
+        return;
+    }
+
+    commands.insert_resource(Restoring);
+
+    let db = db.0.clone();
+    let save_lock = save_lock.clone();
+    let path = event.path.clone();
+    let dir = backups_dir(&selection.dir());
+    // One more than usual, so that making it never deletes the backup being restored
+    let retention = settings.retention.max(1) + 1;
+
+    runtime.spawn_background_task(move |mut ctx| async move {
+        // Hold off saving, so that nothing from before the restore is written over it
+        let _guard = save_lock.lock().await;
+
+        // Keep the world as it was, in case the wrong backup was picked
+        let result = match retry("back up the world", || create_backup(&db, &dir, retention)).await
+        {
+            Ok(_) => retry("restore backup", || restore_backup(&db, &path)).await,
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            report(&mut ctx, error).await;
+
+            ctx.run_on_main_thread(|ctx| ctx.world.remove_resource::<Restoring>())
+                .await;
+
+            return;
+        }
+
+        ctx.run_on_main_thread(|ctx| reset_level(ctx.world)).await;
+        load_player(&mut ctx, &db).await;
+
+        ctx.run_on_main_thread(|ctx| ctx.world.remove_resource::<Restoring>())
+            .await;
+
+        info!("Restored the world from {}", path.display());
+    });
+}
+
+/// Forgets every loaded, cached and unsaved chunk, so that they're loaded again from the database.
+fn reset_level(world: &mut World) {
+    let mut level = world.resource_mut::<Level>();
+    level.panicked.clear();
+    let chunks = std::mem::take(&mut level.chunks);
+
+    for entity in chunks.into_values().filter_map(|loaded| loaded.entity) {
+        world.entity_mut(entity).despawn_recursive();
+    }
+
+    *world.resource_mut::<ChunkCache>() = ChunkCache::default();
+
+    let mut queue = world.resource_mut::<ChunkGenerationQueue>();
+    queue.pending.clear();
+    queue.epoch += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn backups_in_the_same_second_are_kept_apart() {
+        let dir = env::temp_dir().join(format!("defaria-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        let first = create_backup(&db, &dir, 10).await.unwrap();
+        let second = create_backup(&db, &dir, 10).await.unwrap();
+        assert_ne!(first, second);
+
+        let backups = list_backups(&dir).unwrap();
+        let paths: Vec<_> = backups.iter().map(|backup| &backup.path).collect();
+        assert_eq!(paths, [&second, &first]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{chunk::Chunk, position::ChunkPos};
+
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct ChunkCacheSettings {
+    /// Roughly how many bytes of unloaded chunks to keep around. Chunks that haven't been
+    /// saved yet are kept regardless, until they've been written to the database.
+    pub memory_budget: usize,
+}
+
+impl Default for ChunkCacheSettings {
+    fn default() -> Self {
+        Self {
+            memory_budget: 32 * 1024 * 1024,
+        }
+    }
+}
+
+/// Chunks that have been unloaded recently, so that walking back to them doesn't need to read
+/// them from the database again. Chunks that were modified stay dirty until the save that
+/// contains them has been committed, so chunk loading needs to check here before reading from
+/// the database. The least recently unloaded clean chunks are evicted once over budget.
+#[derive(Debug, Default, Resource)]
+pub(super) struct ChunkCache {
+    chunks: HashMap<ChunkPos, CachedChunk>,
+    order: BTreeMap<u64, ChunkPos>,
+    next_tick: u64,
+    memory_used: usize,
+    in_flight: HashSet<ChunkPos>,
+}
+
+#[derive(Debug)]
+struct CachedChunk {
+    chunk: Chunk,
+    tick: u64,
+    /// Set for chunks that haven't been saved yet, and bumped every time the chunk is unloaded
+    /// again, so that a save only marks the chunk clean if it saved this version of it.
+    dirty: Option<u64>,
+}
+
+impl ChunkCache {
+    pub fn insert(&mut self, pos: ChunkPos, mut chunk: Chunk, dirty: bool) {
+        self.remove(pos);
+
+        chunk.optimize();
+
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        self.memory_used += chunk.memory_size();
+        self.order.insert(tick, pos);
+        self.chunks.insert(
+            pos,
+            CachedChunk {
+                chunk,
+                tick,
+                dirty: dirty.then_some(tick),
+            },
+        );
+    }
+
+    /// Takes a chunk out of the cache, along with whether it still needs saving.
+    pub fn take(&mut self, pos: ChunkPos) -> Option<(Chunk, bool)> {
+        self.remove(pos)
+            .map(|cached| (cached.chunk, cached.dirty.is_some()))
+    }
+
+    /// Chunks that haven't been saved yet, along with the version of them being saved.
+    pub fn dirty(&self) -> impl Iterator<Item = (ChunkPos, u64, &Chunk)> {
+        self.chunks
+            .iter()
+            .filter_map(|(&pos, cached)| Some((pos, cached.dirty?, &cached.chunk)))
+    }
+
+    /// Marks a chunk as saved, unless it has been unloaded again since that save was made.
+    pub fn mark_saved(&mut self, pos: ChunkPos, version: u64) {
+        if let Some(cached) = self.chunks.get_mut(&pos) {
+            if cached.dirty == Some(version) {
+                cached.dirty = None;
+            }
+        }
+    }
+
+    pub fn start_saving(&mut self, positions: impl IntoIterator<Item = ChunkPos>) {
+        self.in_flight.extend(positions);
+    }
+
+    pub fn finish_saving(&mut self) {
+        self.in_flight.clear();
+    }
+
+    /// Chunks that are part of a save that hasn't finished yet.
+    pub fn in_flight(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.in_flight.iter().copied()
+    }
+
+    /// Whether the chunk is part of a save that hasn't finished yet, in which case it
+    /// needs to be kept dirty if it's unloaded, in case that save fails.
+    pub fn is_in_flight(&self, pos: ChunkPos) -> bool {
+        self.in_flight.contains(&pos)
+    }
+
+    /// Drops the least recently unloaded clean chunks until the cache fits in its budget.
+    pub fn evict(&mut self, memory_budget: usize) {
+        if self.memory_used <= memory_budget {
+            return;
+        }
+
+        let candidates: Vec<ChunkPos> = self
+            .order
+            .values()
+            .filter(|pos| self.chunks[*pos].dirty.is_none())
+            .copied()
+            .collect();
+
+        for pos in candidates {
+            if self.memory_used <= memory_budget {
+                break;
+            }
+
+            self.remove(pos);
+        }
+    }
+
+    fn remove(&mut self, pos: ChunkPos) -> Option<CachedChunk> {
+        let cached = self.chunks.remove(&pos)?;
+        self.order.remove(&cached.tick);
+        self.memory_used -= cached.chunk.memory_size();
+        Some(cached)
+    }
+}
+
+pub(super) fn evict_cached_chunks(
+    mut cache: ResMut<ChunkCache>,
+    settings: Res<ChunkCacheSettings>,
+) {
+    cache.evict(settings.memory_budget);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached(cache: &ChunkCache) -> Vec<ChunkPos> {
+        cache.order.values().copied().collect()
+    }
+
+    fn dirty_version(cache: &ChunkCache, pos: ChunkPos) -> Option<u64> {
+        cache
+            .dirty()
+            .find(|(dirty_pos, ..)| *dirty_pos == pos)
+            .map(|(_, version, _)| version)
+    }
+
+    #[test]
+    fn least_recently_unloaded_chunks_are_evicted_first() {
+        let size = Chunk::new().memory_size();
+        let (a, b, c) = (ChunkPos::X, ChunkPos::Y, ChunkPos::Z);
+
+        let mut cache = ChunkCache::default();
+        cache.insert(a, Chunk::new(), false);
+        cache.insert(b, Chunk::new(), false);
+        cache.insert(c, Chunk::new(), false);
+
+        // Unloading a chunk again makes it the most recent
+        let (chunk, _) = cache.take(a).unwrap();
+        cache.insert(a, chunk, false);
+        assert_eq!(cached(&cache), [b, c, a]);
+
+        cache.evict(3 * size);
+        assert_eq!(cached(&cache), [b, c, a]);
+
+        cache.evict(2 * size);
+        assert_eq!(cached(&cache), [c, a]);
+        assert_eq!(cache.memory_used, 2 * size);
+
+        cache.evict(0);
+        assert!(cached(&cache).is_empty());
+        assert_eq!(cache.memory_used, 0);
+    }
+
+    #[test]
+    fn dirty_chunks_are_kept_over_budget() {
+        let (a, b) = (ChunkPos::X, ChunkPos::Y);
+
+        let mut cache = ChunkCache::default();
+        cache.insert(a, Chunk::new(), true);
+        cache.insert(b, Chunk::new(), false);
+
+        cache.evict(0);
+        assert_eq!(cached(&cache), [a]);
+
+        let version = dirty_version(&cache, a).unwrap();
+        cache.mark_saved(a, version);
+        cache.evict(0);
+        assert!(cached(&cache).is_empty());
+    }
+
+    #[test]
+    fn saving_an_older_version_keeps_the_chunk_dirty() {
+        let pos = ChunkPos::X;
+
+        let mut cache = ChunkCache::default();
+        cache.insert(pos, Chunk::new(), true);
+        let saving = dirty_version(&cache, pos).unwrap();
+
+        // The chunk is loaded, changed and unloaded again while that save is running
+        let (chunk, dirty) = cache.take(pos).unwrap();
+        assert!(dirty);
+        cache.insert(pos, chunk, true);
+
+        cache.mark_saved(pos, saving);
+        let latest = dirty_version(&cache, pos).unwrap();
+        assert_ne!(latest, saving);
+
+        cache.mark_saved(pos, latest);
+        assert_eq!(dirty_version(&cache, pos), None);
+        assert_eq!(cache.take(pos).map(|(_, dirty)| dirty), Some(false));
+    }
+}
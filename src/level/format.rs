@@ -0,0 +1,306 @@
+//! Every blob stored in the level database is wrapped in a small envelope:
+//! the [`MAGIC`] bytes, followed by a little-endian `u16` format version, followed by
+//! the bincode payload. Blobs written before the envelope existed have no magic bytes,
+//! and are treated as version 0.
+//!
+//! When the layout of a stored type changes (for example, a [`Block`](crate::block::Block) variant is added
+//! in the middle of the enum, or an [`ItemKind`](crate::item::ItemKind) gains a field),
+//! bump its version and push a function onto its upgrade list that converts a payload
+//! of the previous version into the new one. Upgrades are applied in order when old
+//! data is loaded, so worlds from any earlier version can still be opened.
+
+use std::{error::Error, fmt};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    chunk::{Chunk, ChunkDelta},
+    inventory::Inventory,
+    position::CHUNK_INDICES,
+};
+
+const MAGIC: [u8; 4] = *b"DFRA";
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// Converts a payload of one version into a payload of the next version.
+type Upgrade = fn(&[u8]) -> Result<Vec<u8>, FormatError>;
+
+/// Upgrades for chunk data, where the upgrade at index `n` converts version `n` to `n + 1`.
+const CHUNK_UPGRADES: &[Upgrade] = &[upgrade_flat_chunk];
+
+/// Upgrades for chunk delta data, where the upgrade at index `n` converts version `n` to `n + 1`.
+/// Deltas were introduced after the envelope, so there is no unversioned format.
+const CHUNK_DELTA_UPGRADES: &[Upgrade] = &[];
+
+/// Upgrades for inventory data, where the upgrade at index `n` converts version `n` to `n + 1`.
+const INVENTORY_UPGRADES: &[Upgrade] = &[upgrade_unversioned_inventory];
+
+#[derive(Debug)]
+pub enum FormatError {
+    UnknownVersion { version: u16, latest: u16 },
+    Decode(bincode::Error),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownVersion { version, latest } => write!(
+                f,
+                "data has format version {version}, but the latest known version is {latest}"
+            ),
+            Self::Decode(error) => write!(f, "failed to decode data: {error}"),
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+impl From<bincode::Error> for FormatError {
+    fn from(error: bincode::Error) -> Self {
+        Self::Decode(error)
+    }
+}
+
+/// A decoded value, along with whether it had to be upgraded from an older format.
+/// Upgraded values should be written back so the upgrade doesn't run on every load.
+#[derive(Debug, Clone)]
+pub struct Decoded<T> {
+    pub value: T,
+    pub upgraded: bool,
+}
+
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    encode(CHUNK_UPGRADES, chunk)
+}
+
+pub fn decode_chunk(data: &[u8]) -> Result<Decoded<Chunk>, FormatError> {
+    decode(CHUNK_UPGRADES, data)
+}
+
+pub fn encode_chunk_delta(delta: &ChunkDelta) -> Vec<u8> {
+    encode(CHUNK_DELTA_UPGRADES, delta)
+}
+
+pub fn decode_chunk_delta(data: &[u8]) -> Result<Decoded<ChunkDelta>, FormatError> {
+    decode(CHUNK_DELTA_UPGRADES, data)
+}
+
+pub fn encode_inventory(inventory: &Inventory) -> Vec<u8> {
+    encode(INVENTORY_UPGRADES, inventory)
+}
+
+pub fn decode_inventory(data: &[u8]) -> Result<Decoded<Inventory>, FormatError> {
+    decode(INVENTORY_UPGRADES, data)
+}
+
+fn encode<T: Serialize>(upgrades: &[Upgrade], value: &T) -> Vec<u8> {
+    let version = upgrades.len() as u16;
+    let mut data = Vec::new();
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&version.to_le_bytes());
+    bincode::serialize_into(&mut data, value).expect("serializing to a Vec can't fail");
+    data
+}
+
+fn decode<T: DeserializeOwned>(
+    upgrades: &[Upgrade],
+    data: &[u8],
+) -> Result<Decoded<T>, FormatError> {
+    let latest = upgrades.len() as u16;
+
+    let (version, payload) = if data.len() >= HEADER_LEN && data[..MAGIC.len()] == MAGIC {
+        let version = u16::from_le_bytes([data[MAGIC.len()], data[MAGIC.len() + 1]]);
+        (version, &data[HEADER_LEN..])
+    } else {
+        (0, data)
+    };
+
+    if version > latest {
+        return Err(FormatError::UnknownVersion { version, latest });
+    }
+
+    if version == latest {
+        return Ok(Decoded {
+            value: bincode::deserialize(payload)?,
+            upgraded: false,
+        });
+    }
+
+    let mut payload = payload.to_vec();
+
+    for upgrade in &upgrades[version as usize..] {
+        payload = upgrade(&payload)?;
+    }
+
+    Ok(Decoded {
+        value: bincode::deserialize(&payload)?,
+        upgraded: true,
+    })
+}
+
+/// Version 0 chunks stored every block in a flat list, rather than in a palette.
+/// This writes the version 1 layout from its own frozen copy rather than through [`Chunk`],
+/// so that later changes to how chunks are stored can't change what it produces. Blocks are
+/// kept as the variant indices bincode writes for them, so they pass through unchanged.
+fn upgrade_flat_chunk(data: &[u8]) -> Result<Vec<u8>, FormatError> {
+    #[derive(Deserialize)]
+    struct FlatChunkV0 {
+        blocks: Vec<u32>,
+    }
+
+    #[derive(Serialize)]
+    struct ChunkV1 {
+        storage: ChunkStorageV1,
+    }
+
+    #[derive(Serialize)]
+    enum ChunkStorageV1 {
+        Single(u32),
+        Paletted(PalettedBlocksV1),
+    }
+
+    #[derive(Serialize)]
+    struct PalettedBlocksV1 {
+        palette: Vec<u32>,
+        bits: u32,
+        words: Vec<u64>,
+    }
+
+    /// Air was the first variant, and filled in any blocks missing from the list.
+    const AIR_V0: u32 = 0;
+
+    let flat: FlatChunkV0 = bincode::deserialize(data)?;
+
+    let blocks: Vec<u32> = flat
+        .blocks
+        .into_iter()
+        .chain(std::iter::repeat(AIR_V0))
+        .take(CHUNK_INDICES)
+        .collect();
+
+    let mut palette = Vec::new();
+
+    for &block in &blocks {
+        if !palette.contains(&block) {
+            palette.push(block);
+        }
+    }
+
+    let storage = if let [block] = palette[..] {
+        ChunkStorageV1::Single(block)
+    } else {
+        let mut bits = 1;
+        while 1 << bits < palette.len() {
+            bits *= 2;
+        }
+
+        let per_word = 64 / bits as usize;
+        let mut words = vec![0; CHUNK_INDICES.div_ceil(per_word)];
+
+        for (index, block) in blocks.iter().enumerate() {
+            let palette_index = palette.iter().position(|entry| entry == block).unwrap_or(0);
+            let shift = (index % per_word) as u32 * bits;
+            words[index / per_word] |= (palette_index as u64) << shift;
+        }
+
+        ChunkStorageV1::Paletted(PalettedBlocksV1 {
+            palette,
+            bits,
+            words,
+        })
+    };
+
+    Ok(bincode::serialize(&ChunkV1 { storage })?)
+}
+
+/// Version 0 inventories have the same layout as version 1, they just predate the envelope.
+fn upgrade_unversioned_inventory(data: &[u8]) -> Result<Vec<u8>, FormatError> {
+    Ok(data.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::Block, position::LocalPos};
+
+    fn test_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+
+        for index in 0..CHUNK_INDICES {
+            let block = match index % 5 {
+                0 => Block::Rock,
+                1 => Block::Dirt,
+                2 => Block::Grass,
+                _ => Block::Air,
+            };
+
+            chunk.set(LocalPos::from_index(index), block);
+        }
+
+        chunk
+    }
+
+    fn blocks(chunk: &Chunk) -> Vec<Block> {
+        (0..CHUNK_INDICES)
+            .map(|index| chunk.get(LocalPos::from_index(index)))
+            .collect()
+    }
+
+    #[test]
+    fn envelope_round_trips() {
+        let chunk = test_chunk();
+        let data = encode_chunk(&chunk);
+
+        assert_eq!(data[..MAGIC.len()], MAGIC);
+
+        let decoded = decode_chunk(&data).unwrap();
+        assert!(!decoded.upgraded);
+        assert_eq!(blocks(&decoded.value), blocks(&chunk));
+    }
+
+    #[test]
+    fn flat_chunks_are_upgraded() {
+        #[derive(Serialize)]
+        struct FlatChunk {
+            blocks: Vec<Block>,
+        }
+
+        let chunk = test_chunk();
+        let flat = bincode::serialize(&FlatChunk {
+            blocks: blocks(&chunk),
+        })
+        .unwrap();
+
+        let decoded = decode_chunk(&flat).unwrap();
+        assert!(decoded.upgraded);
+        assert_eq!(blocks(&decoded.value), blocks(&chunk));
+
+        // Chunks of a single block collapse into one, just like freshly generated ones do
+        let solid = bincode::serialize(&FlatChunk {
+            blocks: vec![Block::Rock; CHUNK_INDICES],
+        })
+        .unwrap();
+
+        assert!(decode_chunk(&solid).unwrap().value.is_opaque());
+    }
+
+    #[test]
+    fn future_versions_are_rejected() {
+        let mut data = encode_chunk(&test_chunk());
+        let latest = CHUNK_UPGRADES.len() as u16;
+        data[MAGIC.len()..HEADER_LEN].copy_from_slice(&(latest + 1).to_le_bytes());
+
+        assert!(matches!(
+            decode_chunk(&data),
+            Err(FormatError::UnknownVersion { version, .. }) if version == latest + 1
+        ));
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let mut data = encode_chunk(&test_chunk());
+        data[0] = b'X';
+
+        assert!(matches!(decode_chunk(&data), Err(FormatError::Decode(_))));
+    }
+}
@@ -1,9 +1,11 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
+pub mod biome;
+mod hash;
+pub mod preset;
+pub mod structure;
+mod water;
 
-use bevy::prelude::*;
-use noise::{NoiseFn, Perlin, Seedable};
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha8Rng;
+use bevy::{math::DVec3, prelude::*};
+use noise::{NoiseFn, Perlin};
 
 use crate::{
     block::Block,
@@ -11,135 +13,468 @@ use crate::{
     position::{BlockPos, ChunkPos, LocalPos, CHUNK_SIZE},
 };
 
-const TREE_HEIGHT: i32 = 6; // Tall but not gigantic
-const TREE_RADIUS: i32 = 5; // Reasonable canopy size
-const STRUCTURE_ATTEMPT_SPACING: i32 = 10; // Closer base spacing
+use biome::{Biome, BiomeRegistry, BiomeSample, Climate};
+use preset::WorldPreset;
+use structure::{Structure, StructureRegistry};
+use water::Waterways;
+
+pub use water::DEFAULT_SEA_LEVEL;
+
+/// Bumped whenever a change to the generator would produce different terrain for the same seed.
+pub const GENERATOR_VERSION: u32 = 6;
+
+/// Large open caverns, where the density noise is above the threshold.
+const CHEESE_CAVE_SCALE: DVec3 = DVec3::new(40.0, 24.0, 40.0);
+const CHEESE_CAVE_THRESHOLD: f64 = 0.55;
+/// How far below the surface caverns start, so that they don't leave thin crusts of terrain.
+const CHEESE_CAVE_MIN_DEPTH: i32 = 8;
+
+/// Winding tunnels, along the lines where two noise fields both cross zero.
+const SPAGHETTI_CAVE_SCALE: DVec3 = DVec3::new(48.0, 32.0, 48.0);
+const SPAGHETTI_CAVE_RADIUS: f64 = 0.08;
+/// How many blocks under water caves stay away from, so that they don't open up under it.
+const CAVE_WATER_SEAL: i32 = 4;
+
+/// The columns next to a column, as offsets along x and z.
+const HORIZONTAL_NEIGHBORS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
 
 #[derive(Debug, Default, Clone, Resource)]
 pub struct LevelGenerator {
+    seed: u32,
+    preset: WorldPreset,
     density_noise: Perlin,
     terrain_noise: Perlin,
     moisture_noise: Perlin,
+    temperature_noise: Perlin,
+    overhang_noise: Perlin,
+    cave_noise: Perlin,
+    waterways: Waterways,
+    biomes: BiomeRegistry,
+    structures: StructureRegistry,
+    /// Every spacing biomes place structures at, each with its own grid of attempts.
+    structure_spacings: Vec<i32>,
+}
+
+/// A spot a structure is attempted at, along with the randomness for picking and shaping it.
+#[derive(Debug, Clone, Copy)]
+struct StructureSpot {
+    x: i32,
+    z: i32,
+    roll: f32,
+    seed: u64,
+}
+
+/// A structure placed in the world.
+#[derive(Debug, Clone, Copy)]
+struct Placement<'a> {
+    origin: BlockPos,
+    structure: &'a dyn Structure,
+    seed: u64,
+}
+
+/// The columns a chunk is generated from, each worked out once instead of for every block,
+/// with a border one column wide around the chunk for caves to check for water next to it.
+/// Columns further out are worked out whenever they're needed.
+struct ColumnCache<'a> {
+    generator: &'a LevelGenerator,
+    min_x: i32,
+    min_z: i32,
+    columns: Vec<Column<'a>>,
+}
+
+impl<'a> ColumnCache<'a> {
+    const WIDTH: usize = CHUNK_SIZE + 2;
+
+    fn new(generator: &'a LevelGenerator, chunk_pos: ChunkPos) -> Self {
+        let origin = LocalPos::new(0, 0, 0).block_pos(chunk_pos);
+        let min_x = origin.x - 1;
+        let min_z = origin.z - 1;
+
+        let columns = (0..Self::WIDTH * Self::WIDTH)
+            .map(|index| {
+                let x = min_x + (index / Self::WIDTH) as i32;
+                let z = min_z + (index % Self::WIDTH) as i32;
+                generator.column(x, z)
+            })
+            .collect();
+
+        Self {
+            generator,
+            min_x,
+            min_z,
+            columns,
+        }
+    }
+
+    fn get(&self, x: i32, z: i32) -> Column<'a> {
+        let index = |value: i32| usize::try_from(value).ok().filter(|&i| i < Self::WIDTH);
+
+        match (index(x - self.min_x), index(z - self.min_z)) {
+            (Some(x), Some(z)) => self.columns[x * Self::WIDTH + z],
+            _ => self.generator.column(x, z),
+        }
+    }
+}
+
+/// What the generator needs to know about a column of terrain.
+#[derive(Debug, Clone, Copy)]
+struct Column<'a> {
+    /// The height of the terrain before it's bent into overhangs.
+    height: i32,
+    biome: BiomeSample<'a>,
+    /// How far terrain can be pushed in or out from the column's height.
+    overhang: f64,
+    /// How high water fills the column, if it has any.
+    water_level: Option<i32>,
+    /// Replaces the biome's surface and subsurface on beaches and under water.
+    shore: Option<Block>,
+}
+
+impl Column<'_> {
+    /// Whether a position in the column is filled with water, as long as there's no terrain
+    /// there. Columns with water never overhang, so this is everything above their height.
+    fn is_water(&self, y: i32) -> bool {
+        self.water_level
+            .is_some_and(|level| y > self.height && y <= level)
+    }
+
+    fn surface(&self, generator: &LevelGenerator, x: i32, z: i32) -> Block {
+        self.shore
+            .unwrap_or_else(|| generator.surface_block(self.biome.biome, x, z))
+    }
+
+    fn subsurface(&self) -> Block {
+        self.shore.unwrap_or(self.biome.biome.subsurface)
+    }
+
+    fn subsurface_depth(&self, generator: &LevelGenerator) -> i32 {
+        match self.shore {
+            Some(_) => self
+                .biome
+                .biome
+                .subsurface_depth
+                .max(generator.preset.shore_depth),
+            None => self.biome.biome.subsurface_depth,
+        }
+    }
 }
 
 impl LevelGenerator {
     pub fn new(seed: u32) -> Self {
+        Self::with_preset(seed, WorldPreset::default())
+    }
+
+    /// # Panics
+    ///
+    /// If a biome spawns a structure that isn't registered, or spawns structures less than
+    /// a block apart. Presets loaded with [`WorldPreset::from_ron`] are checked for this.
+    pub fn with_preset(seed: u32, preset: WorldPreset) -> Self {
+        let mut structures = StructureRegistry::default();
+        preset.register_trees(&mut structures);
+
+        let biomes = BiomeRegistry::new(
+            BiomeRegistry::default()
+                .biomes()
+                .iter()
+                .map(|biome| {
+                    let mut biome = biome.clone();
+                    biome.base_height =
+                        biome.base_height * preset.height_scale + preset.height_offset;
+                    biome.height_amplitude *= preset.height_scale;
+
+                    for spawn in &mut biome.structures {
+                        spawn.spacing = preset.scale_spacing(spawn.spacing);
+                    }
+
+                    biome
+                })
+                .collect(),
+        );
+
+        let mut structure_spacings = Vec::new();
+
+        for spawn in biomes.biomes().iter().flat_map(|biome| &biome.structures) {
+            assert!(
+                structures.get(spawn.structure).is_some(),
+                "unknown structure {}",
+                spawn.structure
+            );
+            assert!(
+                spawn.spacing > 0,
+                "structures need to be at least a block apart"
+            );
+
+            if !structure_spacings.contains(&spawn.spacing) {
+                structure_spacings.push(spawn.spacing);
+            }
+        }
+
         Self {
+            seed,
             density_noise: Perlin::new(seed),
             terrain_noise: Perlin::new(seed + 1),
             moisture_noise: Perlin::new(seed + 2),
+            temperature_noise: Perlin::new(seed + 3),
+            overhang_noise: Perlin::new(seed + 4),
+            cave_noise: Perlin::new(seed + 5),
+            waterways: Waterways::new(seed + 6, &preset),
+            biomes,
+            structures,
+            structure_spacings,
+            preset,
+        }
+    }
+
+    pub fn preset(&self) -> &WorldPreset {
+        &self.preset
+    }
+
+    pub fn sea_level(&self) -> i32 {
+        self.waterways.sea_level()
+    }
+
+    pub fn biomes(&self) -> &BiomeRegistry {
+        &self.biomes
+    }
+
+    pub fn climate(&self, x: i32, z: i32) -> Climate {
+        let scale = self.preset.climate_scale;
+        let contrast = self.preset.climate_contrast;
+        let point = [x as f64 / scale, z as f64 / scale];
+        let stretch = |value: f64| ((value * contrast + 1.0) / 2.0).clamp(0.0, 1.0);
+
+        Climate::new(
+            stretch(self.temperature_noise.get(point)),
+            stretch(self.moisture_noise.get(point)),
+        )
+    }
+
+    /// The biome a column belongs to.
+    pub fn biome(&self, x: i32, z: i32) -> &Biome {
+        self.biomes.sample(self.climate(x, z)).biome
+    }
+
+    fn column(&self, x: i32, z: i32) -> Column<'_> {
+        let biome = self.biomes.sample(self.climate(x, z));
+        let shape = self.terrain_shape(x as f64, z as f64);
+        let height = biome.base_height + shape * biome.height_amplitude;
+
+        let shore = self.waterways.shape(x, z, height, &biome);
+        let height = shore.height.round() as i32;
+        let sea_level = self.sea_level();
+
+        let ground = match shore.water_level {
+            Some(level) if level - height > self.preset.deep_water => Some(Block::Gravel),
+            Some(_) => Some(Block::Sand),
+            None if height <= sea_level + self.preset.beach_height => Some(Block::Sand),
+            None => None,
+        };
+
+        Column {
+            height,
+            biome,
+            overhang: if shore.water_level.is_some() {
+                0.0
+            } else {
+                biome.height_amplitude * self.preset.overhang_factor * (1.0 - shore.bank)
+            },
+            water_level: shore.water_level,
+            shore: ground,
         }
     }
 
-    fn get_moisture(&self, pos: &Vec3) -> f64 {
-        (self.moisture_noise.get([
-            pos.x as f64 * 0.003,
-            pos.y as f64 * 0.01,
-            pos.z as f64 * 0.003,
-        ]) + 1.0)
-            / 2.0
+    /// Whether there's terrain at a position, before caves are carved out of it. Near the
+    /// column's height this follows 3D noise, which makes cliffs and overhangs, except at
+    /// or below sea level where it could leave holes next to the sea.
+    fn is_terrain(&self, column: &Column, pos: BlockPos) -> bool {
+        let depth = (column.height - pos.y) as f64;
+        let overhang = column.overhang;
+
+        if depth >= overhang || (pos.y <= self.sea_level() && depth >= 0.0) {
+            return true;
+        }
+
+        if depth < -overhang || pos.y <= self.sea_level() {
+            return false;
+        }
+
+        let point = pos.world_pos().as_dvec3() / self.preset.overhang_scale;
+        depth + self.overhang_noise.get(point.to_array()) * overhang >= 0.0
+    }
+
+    /// Whether a cave carves out a position. Caves stay sealed under water, but this doesn't
+    /// know about the water in other columns, see [`Self::is_open_cave`].
+    fn is_cave(&self, column: &Column, pos: BlockPos) -> bool {
+        if !self.preset.caves {
+            return false;
+        }
+
+        if column.water_level.is_some() && pos.y > column.height - CAVE_WATER_SEAL {
+            return false;
+        }
+
+        let point = pos.world_pos().as_dvec3();
+
+        if column.height - pos.y >= CHEESE_CAVE_MIN_DEPTH {
+            let cheese = self
+                .density_noise
+                .get((point / CHEESE_CAVE_SCALE).to_array());
+
+            if cheese > CHEESE_CAVE_THRESHOLD {
+                return true;
+            }
+        }
+
+        let point = point / SPAGHETTI_CAVE_SCALE;
+        let a = self.cave_noise.get(point.to_array());
+        // Far enough away in the same noise to be unrelated
+        let b = self
+            .cave_noise
+            .get((point + DVec3::splat(1000.5)).to_array());
+
+        a * a + b * b < SPAGHETTI_CAVE_RADIUS * SPAGHETTI_CAVE_RADIUS
+    }
+
+    /// Whether a cave carves out a position without opening into water next to it, given the
+    /// columns on each side of it.
+    fn is_open_cave(&self, column: &Column, neighbors: &[Column; 4], pos: BlockPos) -> bool {
+        self.is_cave(column, pos)
+            && !column.is_water(pos.y + 1)
+            && !neighbors.iter().any(|neighbor| neighbor.is_water(pos.y))
+    }
+
+    /// The highest terrain block in a column, which overhangs can put above its height.
+    fn top(&self, column: &Column, x: i32, z: i32) -> i32 {
+        let reach = column.overhang.ceil() as i32;
+
+        // Everything at least the reach below the height is terrain
+        (column.height - reach..=column.height + reach)
+            .rev()
+            .find(|&y| self.is_terrain(column, BlockPos::new(x, y, z)))
+            .unwrap_or(column.height - reach)
     }
 
-    fn get_structure_rng(&self, pos: BlockPos) -> ChaCha8Rng {
-        let mut hasher = DefaultHasher::new();
-        pos.hash(&mut hasher);
-        let hash = hasher.finish();
-        ChaCha8Rng::seed_from_u64(hash ^ self.density_noise.seed() as u64)
+    fn surface_block(&self, biome: &Biome, x: i32, z: i32) -> Block {
+        let Some(patch) = biome.surface_patches else {
+            return biome.surface;
+        };
+
+        let frequency = self.preset.surface_patch_frequency;
+        let patch_noise = self
+            .terrain_noise
+            .get([x as f64 * frequency, z as f64 * frequency, 0.0]);
+
+        if patch_noise > self.preset.surface_patch_threshold {
+            patch
+        } else {
+            biome.surface
+        }
     }
 
-    fn get_height(&self, x: f64, z: f64) -> i32 {
-        // Create a more dramatic heightmap using our terrain noise
-        let base_height = self.terrain_noise.get([x * 0.01, z * 0.01, 0.0]);
+    /// The shape of the terrain, roughly between -1 and 1, before each biome scales it.
+    fn terrain_shape(&self, x: f64, z: f64) -> f64 {
+        // From broad hills down to small bumps
+        self.preset
+            .terrain_octaves
+            .iter()
+            .map(|octave| {
+                let point = [x * octave.frequency, z * octave.frequency, 0.0];
+                self.terrain_noise.get(point) * octave.amplitude
+            })
+            .sum()
+    }
 
-        // Add medium-scale variation
-        let medium_detail = self.terrain_noise.get([x * 0.05, z * 0.05, 0.0]) * 0.3;
+    /// How far above the surface structures can reach.
+    pub fn max_structure_height(&self) -> i32 {
+        // Structures start one block above the surface
+        self.structures.bounds().1.y + 1
+    }
 
-        // Add small-scale detail
-        let small_detail = self.terrain_noise.get([x * 0.1, z * 0.1, 0.0]) * 0.1;
+    /// The y coordinate of the highest terrain or water block in a column, ignoring
+    /// structures and caves.
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let column = self.column(x, z);
+        let top = self.top(&column, x, z);
 
-        // Combine and amplify the height variations
-        // Multiply by 16 instead of 4 for more dramatic heights
-        ((base_height + medium_detail + small_detail) * 16.0).round() as i32
+        column.water_level.map_or(top, |level| top.max(level))
     }
 
-    pub fn generate_chunk(&mut self, chunk_pos: ChunkPos) -> Chunk {
+    pub fn generate_chunk(&self, chunk_pos: ChunkPos) -> Chunk {
         let mut chunk = Chunk::new();
 
+        let min_y = chunk_pos.y * CHUNK_SIZE as i32;
+        let max_y = min_y + CHUNK_SIZE as i32 - 1;
+
+        let origin = LocalPos::new(0, 0, 0).block_pos(chunk_pos);
+        let columns = ColumnCache::new(self, chunk_pos);
+
         // Generate terrain
         for x in 0..CHUNK_SIZE {
             for z in 0..CHUNK_SIZE {
-                for y in 0..CHUNK_SIZE {
-                    let local_pos = LocalPos::new(x, y, z);
-                    let block_pos = local_pos.block_pos(chunk_pos);
-                    let world_pos = block_pos.world_pos();
-                    let height = self.get_height(world_pos.x as f64, world_pos.z as f64);
-
-                    if block_pos.y <= height {
-                        let moisture = self.get_moisture(&world_pos);
-
-                        let block_type = if block_pos.y == height {
-                            // Surface layer
-                            if moisture > 0.3 {
-                                let gravel_noise = self.terrain_noise.get([
-                                    world_pos.x as f64 * 0.08,
-                                    world_pos.z as f64 * 0.08,
-                                    0.0,
-                                ]);
-
-                                if gravel_noise > 0.6 {
-                                    Block::Gravel
-                                } else {
-                                    Block::Grass
-                                }
-                            } else {
-                                Block::Sand
-                            }
-                        } else if block_pos.y >= height - 2 {
-                            // Subsurface layers
-                            if moisture > 0.3 {
-                                Block::Dirt
-                            } else {
-                                Block::Sand
-                            }
-                        } else {
-                            // Deep layers
-                            Block::Rock
-                        };
+                let column_pos = LocalPos::new(x, 0, z).block_pos(chunk_pos);
+                let column = columns.get(column_pos.x, column_pos.z);
+                let neighbors = HORIZONTAL_NEIGHBORS
+                    .map(|(dx, dz)| columns.get(column_pos.x + dx, column_pos.z + dz));
+
+                // How many terrain blocks there are between each block and the air above it.
+                // Counting starts far enough above the chunk to layer its top correctly.
+                let mut depth = i32::MAX;
+
+                for y in (min_y..=max_y + column.subsurface_depth(self) + 1).rev() {
+                    let block_pos = BlockPos::new(column_pos.x, y, column_pos.z);
+
+                    if !self.is_terrain(&column, block_pos) {
+                        depth = 0;
+
+                        if y <= max_y && column.is_water(y) {
+                            chunk.set(block_pos.local_pos(), Block::Water);
+                        }
 
-                        chunk.set(local_pos, block_type);
+                        continue;
                     }
-                }
-            }
-        }
 
-        // Get all possible structure positions that could affect this chunk
-        let structure_positions = self.get_structure_positions_affecting_chunk(chunk_pos);
+                    let block_depth = depth;
+                    depth = depth.saturating_add(1);
 
-        // Try generating structures at each position
-        for pos in structure_positions {
-            // Get the height at this position
-            let height = self.get_height(pos.x as f64, pos.z as f64);
-            let valid_ground = pos.y == height + 1; // Check if we're one block above the surface
+                    if y > max_y || self.is_open_cave(&column, &neighbors, block_pos) {
+                        continue;
+                    }
 
-            if !valid_ground {
-                continue;
+                    let block_type = if block_depth == 0 {
+                        column.surface(self, block_pos.x, block_pos.z)
+                    } else if block_depth <= column.subsurface_depth(self) {
+                        column.subsurface()
+                    } else {
+                        // Deep layers
+                        Block::Rock
+                    };
+
+                    chunk.set(block_pos.local_pos(), block_type);
+                }
             }
+        }
 
-            // Use deterministic RNG for this position
-            let mut rng = self.get_structure_rng(pos);
+        let chunk_max = origin + BlockPos::new(1, 1, 1) * (CHUNK_SIZE as i32 - 1);
 
-            // For each block position in the chunk
-            for x in 0..CHUNK_SIZE {
-                for y in 0..CHUNK_SIZE {
-                    for z in 0..CHUNK_SIZE {
-                        let local_pos = LocalPos::new(x, y, z);
-                        let block_pos = local_pos.block_pos(chunk_pos);
+        for placement in self.structure_placements(chunk_pos, &columns) {
+            let (low, high) = placement.structure.bounds();
+            let low = placement.origin + low;
+            let high = placement.origin + high;
+
+            // Only the part of the structure inside this chunk
+            for x in low.x.max(origin.x)..=high.x.min(chunk_max.x) {
+                for y in low.y.max(origin.y)..=high.y.min(chunk_max.y) {
+                    for z in low.z.max(origin.z)..=high.z.min(chunk_max.z) {
+                        let block_pos = BlockPos::new(x, y, z);
+                        let local_pos = block_pos.local_pos();
+                        let offset = block_pos - placement.origin;
 
-                        // Get the block type from the structure at this position
-                        let structure_block = self.get_tree_block(block_pos, pos, &mut rng);
+                        // Structures grow into open space, but never replace terrain
+                        if chunk.get(local_pos).is_solid() {
+                            continue;
+                        }
 
-                        // If the structure defines a block here, override the terrain
-                        if let Some(block) = structure_block {
+                        if let Some(block) = placement.structure.block(offset, placement.seed) {
                             chunk.set(local_pos, block);
                         }
                     }
@@ -147,103 +482,364 @@ impl LevelGenerator {
             }
         }
 
+        chunk.optimize();
         chunk
     }
 
-    fn get_structure_positions_affecting_chunk(&self, chunk_pos: ChunkPos) -> Vec<BlockPos> {
-        let chunk_min = chunk_pos.world_pos();
-        let chunk_max = (chunk_pos + ChunkPos::new(1, 1, 1)).world_pos();
-        let structure_radius = (0.max(TREE_HEIGHT + TREE_RADIUS) + 1) as f32;
+    /// Every structure that reaches into a chunk, in the same order for every chunk, so that
+    /// where structures overlap they overlap the same way on both sides of a chunk border.
+    fn structure_placements(
+        &self,
+        chunk_pos: ChunkPos,
+        columns: &ColumnCache,
+    ) -> Vec<Placement<'_>> {
+        let (reach_low, reach_high) = self.structures.bounds();
+        let chunk_min = LocalPos::new(0, 0, 0).block_pos(chunk_pos);
+        let chunk_max = chunk_min + BlockPos::new(1, 1, 1) * (CHUNK_SIZE as i32 - 1);
+
+        let mut placements = Vec::new();
+
+        for &spacing in &self.structure_spacings {
+            // Cells with room for a structure that could reach into the chunk
+            let min_x = (chunk_min.x - reach_high.x).div_euclid(spacing);
+            let max_x = (chunk_max.x - reach_low.x).div_euclid(spacing);
+            let min_z = (chunk_min.z - reach_high.z).div_euclid(spacing);
+            let max_z = (chunk_max.z - reach_low.z).div_euclid(spacing);
+
+            for cell_x in min_x..=max_x {
+                for cell_z in min_z..=max_z {
+                    let spot = self.structure_spot(spacing, cell_x, cell_z);
+
+                    // Structures too far away to reach into the chunk don't need their column
+                    // worked out
+                    let in_reach = spot.x + reach_low.x <= chunk_max.x
+                        && spot.x + reach_high.x >= chunk_min.x
+                        && spot.z + reach_low.z <= chunk_max.z
+                        && spot.z + reach_high.z >= chunk_min.z;
+
+                    if !in_reach {
+                        continue;
+                    }
+
+                    let Some(placement) = self.place_structure(spacing, spot, columns) else {
+                        continue;
+                    };
+
+                    let (low, high) = placement.structure.bounds();
+                    let low = placement.origin + low;
+                    let high = placement.origin + high;
+
+                    let overlaps = low.x <= chunk_max.x
+                        && high.x >= chunk_min.x
+                        && low.y <= chunk_max.y
+                        && high.y >= chunk_min.y
+                        && low.z <= chunk_max.z
+                        && high.z >= chunk_min.z;
+
+                    if overlaps {
+                        placements.push(placement);
+                    }
+                }
+            }
+        }
+
+        placements
+    }
+
+    /// Where in a cell of the grid for a spacing a structure would be attempted.
+    fn structure_spot(&self, spacing: i32, cell_x: i32, cell_z: i32) -> StructureSpot {
+        // Each part of the spot gets its own hash, told apart by the first value
+        let part = |part| hash::stable_hash(self.seed.into(), &[part, spacing, cell_x, cell_z]);
 
-        let min_x =
-            ((chunk_min.x - structure_radius) / STRUCTURE_ATTEMPT_SPACING as f32).floor() as i32;
-        let max_x =
-            ((chunk_max.x + structure_radius) / STRUCTURE_ATTEMPT_SPACING as f32).ceil() as i32;
-        let min_z =
-            ((chunk_min.z - structure_radius) / STRUCTURE_ATTEMPT_SPACING as f32).floor() as i32;
-        let max_z =
-            ((chunk_max.z + structure_radius) / STRUCTURE_ATTEMPT_SPACING as f32).ceil() as i32;
+        StructureSpot {
+            x: cell_x * spacing + hash::below(part(0), spacing),
+            z: cell_z * spacing + hash::below(part(1), spacing),
+            roll: hash::unit(part(2)),
+            seed: part(3),
+        }
+    }
+
+    /// Picks the structure at a spot, if the biome there places one.
+    fn place_structure(
+        &self,
+        spacing: i32,
+        spot: StructureSpot,
+        columns: &ColumnCache,
+    ) -> Option<Placement<'_>> {
+        let column = columns.get(spot.x, spot.z);
+        let mut roll = spot.roll;
+
+        // The spawns with this spacing share the cell, each taking its weight of the roll
+        let spawn = column
+            .biome
+            .biome
+            .structures
+            .iter()
+            .filter(|spawn| spawn.spacing == spacing)
+            .find(|spawn| {
+                roll -= spawn.weight;
+                roll < 0.0
+            })?;
+
+        let structure = self.structures.get(spawn.structure)?;
+        let origin = self.structure_origin(structure, &column, spot.x, spot.z)?;
+
+        Some(Placement {
+            origin,
+            structure,
+            seed: spot.seed,
+        })
+    }
+
+    /// Where a structure in a column would start, one block above the surface, as long as
+    /// the ground there can hold it and isn't under water or carved out by a cave.
+    fn structure_origin(
+        &self,
+        structure: &dyn Structure,
+        column: &Column,
+        x: i32,
+        z: i32,
+    ) -> Option<BlockPos> {
+        let ground = BlockPos::new(x, self.top(column, x, z), z);
+
+        let valid_ground = column.water_level.is_none()
+            && structure.can_stand_on(column.surface(self, x, z))
+            && !self.is_cave(column, ground);
+
+        valid_ground.then(|| ground + BlockPos::Y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::position::CHUNK_INDICES;
+
+    use super::*;
+
+    /// Hashes of generated chunks, pinned so that anything changing what the generator produces
+    /// for a seed fails here, including updates to the noise crate. When a change is meant to
+    /// move terrain, bump [`GENERATOR_VERSION`] and replace these with the new hashes.
+    const GOLDEN_CHUNKS: [(u32, &str, [i32; 3], u64); 8] = [
+        (1, "default", [0, 0, 4], 0x4bc1_f035_ef69_0d0b),
+        (1, "default", [1, 0, 0], 0x086a_0ef9_3ff9_f5ba),
+        (12345, "default", [0, 0, 1], 0xb852_b190_5880_194f),
+        (12345, "default", [3, 0, 0], 0x7db9_d323_3bf0_8b03),
+        (12345, "default", [0, -3, 0], 0xd0b5_5653_11d2_3df5),
+        (12345, "flat", [0, 0, 0], 0x8669_0e76_d8c7_aa92),
+        (12345, "amplified", [0, 1, 2], 0x4e3f_2738_d663_357a),
+        (777, "archipelago", [0, -1, 3], 0xfa4e_a5d0_4495_1760),
+    ];
+
+    fn chunk_hash(chunk: &Chunk) -> u64 {
+        let blocks: Vec<i32> = (0..CHUNK_INDICES)
+            .map(|index| chunk.get(LocalPos::from_index(index)) as i32)
+            .collect();
+
+        hash::stable_hash(0, &blocks)
+    }
+
+    #[test]
+    fn chunks_match_golden_hashes() {
+        let mismatches: Vec<String> = GOLDEN_CHUNKS
+            .iter()
+            .filter_map(|&(seed, preset, [x, y, z], expected)| {
+                let preset_settings = WorldPreset::builtin(preset).unwrap();
+                let generator = LevelGenerator::with_preset(seed, preset_settings);
+                let hash = chunk_hash(&generator.generate_chunk(ChunkPos::new(x, y, z)));
+
+                (hash != expected).then(|| {
+                    format!("seed {seed}, {preset} preset, chunk {x} {y} {z}: {hash:#018x}")
+                })
+            })
+            .collect();
+
+        assert!(mismatches.is_empty(), "{mismatches:#?}");
+    }
 
-        let mut positions = Vec::new();
+    /// Checks the side of a chunk that touches a neighbor against the structures the neighbor
+    /// places, and returns how many of the chunk's blocks there came from those structures.
+    fn matching_border_blocks(
+        generator: &LevelGenerator,
+        neighbor_pos: ChunkPos,
+        chunk_pos: ChunkPos,
+    ) -> usize {
+        let chunk = generator.generate_chunk(chunk_pos);
+        let edge = CHUNK_SIZE - 1;
+        let mut matching = 0;
 
-        for x in min_x..=max_x {
-            for z in min_z..=max_z {
-                let base_x = x * STRUCTURE_ATTEMPT_SPACING;
-                let base_z = z * STRUCTURE_ATTEMPT_SPACING;
+        let columns = ColumnCache::new(generator, neighbor_pos);
 
-                // Get the height at this position
-                let height = self.get_height(base_x as f64, base_z as f64);
+        for placement in generator.structure_placements(neighbor_pos, &columns) {
+            let (low, high) = placement.structure.bounds();
+
+            for a in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let local_pos =
+                        match (chunk_pos.x - neighbor_pos.x, chunk_pos.z - neighbor_pos.z) {
+                            (1, 0) => LocalPos::new(0, y, a),
+                            (-1, 0) => LocalPos::new(edge, y, a),
+                            (0, 1) => LocalPos::new(a, y, 0),
+                            _ => LocalPos::new(a, y, edge),
+                        };
 
-                let base_pos = BlockPos::new(
-                    base_x,
-                    height + 1, // One block above surface
-                    base_z,
+                    let block_pos = local_pos.block_pos(chunk_pos);
+                    let offset = block_pos - placement.origin;
+
+                    let inside = (low.x..=high.x).contains(&offset.x)
+                        && (low.y..=high.y).contains(&offset.y)
+                        && (low.z..=high.z).contains(&offset.z);
+
+                    let Some(expected) = placement
+                        .structure
+                        .block(offset, placement.seed)
+                        .filter(|_| inside)
+                    else {
+                        continue;
+                    };
+
+                    // Terrain is kept wherever a structure would overlap it
+                    let actual = chunk.get(local_pos);
+
+                    assert!(
+                        actual == expected || actual.is_solid(),
+                        "expected {expected:?} at {block_pos:?} from a structure at {:?}, \
+                        but chunk {chunk_pos:?} has {actual:?}",
+                        placement.origin
+                    );
+
+                    if actual == expected {
+                        matching += 1;
+                    }
+                }
+            }
+        }
+
+        matching
+    }
+
+    #[test]
+    fn structures_match_across_chunk_borders() {
+        let generator = LevelGenerator::new(12345);
+        let mut matching = 0;
+
+        for x in 0..4 {
+            for z in 0..4 {
+                let pos = ChunkPos::new(x, 0, z);
+
+                for neighbor_pos in [pos + ChunkPos::X, pos + ChunkPos::Z] {
+                    matching += matching_border_blocks(&generator, pos, neighbor_pos);
+                    matching += matching_border_blocks(&generator, neighbor_pos, pos);
+                }
+            }
+        }
+
+        assert!(matching > 0, "no structures crossed a chunk border");
+    }
+
+    /// Checks the side of a chunk that touches a neighbor against the water and shore the
+    /// neighbor's columns say are there, and returns how many water and shore blocks matched.
+    fn matching_water_blocks(
+        generator: &LevelGenerator,
+        neighbor_pos: ChunkPos,
+        chunk_pos: ChunkPos,
+    ) -> (usize, usize) {
+        let chunk = generator.generate_chunk(chunk_pos);
+        let columns = ColumnCache::new(generator, neighbor_pos);
+        let edge = CHUNK_SIZE - 1;
+        let (mut water, mut shore) = (0, 0);
+
+        for a in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let local_pos = match (chunk_pos.x - neighbor_pos.x, chunk_pos.z - neighbor_pos.z) {
+                    (1, 0) => LocalPos::new(0, y, a),
+                    (-1, 0) => LocalPos::new(edge, y, a),
+                    (0, 1) => LocalPos::new(a, y, 0),
+                    _ => LocalPos::new(a, y, edge),
+                };
+
+                let block_pos = local_pos.block_pos(chunk_pos);
+                let column = columns.get(block_pos.x, block_pos.z);
+                let actual = chunk.get(local_pos);
+
+                assert_eq!(
+                    actual == Block::Water,
+                    column.is_water(block_pos.y),
+                    "chunk {chunk_pos:?} has {actual:?} at {block_pos:?}, but the column chunk \
+                    {neighbor_pos:?} sees there has water up to {:?}",
+                    column.water_level
                 );
 
-                let mut cell_rng = self.get_structure_rng(base_pos);
+                if actual == Block::Water {
+                    water += 1;
+                }
 
-                if cell_rng.random::<f32>() < 0.1 {
-                    let offset_x = cell_rng.random_range(-2..3);
-                    let offset_z = cell_rng.random_range(-2..3);
+                // Columns with water are sealed against caves, so their shore is always there
+                let Some(expected) = column.shore.filter(|_| column.water_level.is_some()) else {
+                    continue;
+                };
 
-                    // Get height at the offset position
-                    let final_x = base_x + offset_x;
-                    let final_z = base_z + offset_z;
-                    let final_height = self.get_height(final_x as f64, final_z as f64);
+                if block_pos.y == column.height {
+                    assert_eq!(
+                        actual, expected,
+                        "expected {expected:?} on the shore at {block_pos:?}, as chunk \
+                        {neighbor_pos:?} sees it, but chunk {chunk_pos:?} has {actual:?}"
+                    );
 
-                    let origin = BlockPos::new(final_x, final_height + 1, final_z);
-                    positions.push(origin);
+                    shore += 1;
                 }
             }
         }
 
-        positions
+        (water, shore)
     }
 
-    fn get_tree_block(
-        &self,
-        block_pos: BlockPos,
-        origin: BlockPos,
-        rng: &mut ChaCha8Rng,
-    ) -> Option<Block> {
-        let diff = block_pos - origin;
-
-        // Derive height deterministically from position
-        let mut hasher = DefaultHasher::new();
-        origin.hash(&mut hasher);
-        let height = TREE_HEIGHT + (hasher.finish() % 3) as i32;
-
-        // Trunk
-        if diff.x == 0 && diff.z == 0 && diff.y >= 0 && diff.y < height {
-            return Some(Block::Wood);
-        }
-
-        // Leaves
-        let leaf_start = height - 4;
-        let leaf_height = 5;
-
-        if diff.y >= leaf_start && diff.y < leaf_start + leaf_height {
-            let y_level = diff.y - leaf_start;
-            let radius = if y_level == 0 || y_level == leaf_height - 1 {
-                1
-            } else {
-                2
-            };
-
-            if diff.x.abs() <= radius && diff.z.abs() <= radius {
-                // Skip corners for rounder appearance
-                if diff.x * diff.x + diff.z * diff.z <= radius * radius + 1 {
-                    // Use RNG only for leaf detail variation
-                    if (diff.x.abs() == radius || diff.z.abs() == radius)
-                        && rng.random::<f32>() < 0.5
-                    {
-                        return None;
+    /// The first chunk along the x axis whose middle column has the water being looked for,
+    /// at the height of that water's surface.
+    fn find_water(generator: &LevelGenerator, has_water: impl Fn(i32) -> bool) -> ChunkPos {
+        let half = CHUNK_SIZE as i32 / 2;
+
+        (0..500)
+            .find_map(|x| {
+                let column = generator.column(x * CHUNK_SIZE as i32 + half, half);
+                let level = column.water_level.filter(|&level| has_water(level))?;
+                Some(ChunkPos::new(x, level.div_euclid(CHUNK_SIZE as i32), 0))
+            })
+            .expect("no water found")
+    }
+
+    #[test]
+    fn water_matches_across_chunk_borders() {
+        // Lakes are the only water above the sea. With the land raised out of the sea, the
+        // only water left at sea level is in rivers
+        let lakes = LevelGenerator::new(12345);
+        let rivers = LevelGenerator::with_preset(
+            12345,
+            WorldPreset {
+                lakes: false,
+                height_offset: 20.0,
+                ..default()
+            },
+        );
+
+        let lake = find_water(&lakes, |level| level > lakes.sea_level());
+        let river = find_water(&rivers, |level| level == rivers.sea_level());
+
+        for (generator, pos) in [(&lakes, lake), (&rivers, river)] {
+            let (mut water, mut shore) = (0, 0);
+
+            // Shores and beds can be in the chunk below the water's surface
+            for pos in [pos, pos - ChunkPos::Y] {
+                for neighbor_pos in [pos + ChunkPos::X, pos - ChunkPos::X, pos + ChunkPos::Z] {
+                    for (a, b) in [(pos, neighbor_pos), (neighbor_pos, pos)] {
+                        let matched = matching_water_blocks(generator, a, b);
+                        water += matched.0;
+                        shore += matched.1;
                     }
-                    return Some(Block::Leaves);
                 }
             }
-        }
 
-        None
+            assert!(water > 0, "no water crossed a chunk border around {pos:?}");
+            assert!(shore > 0, "no shore crossed a chunk border around {pos:?}");
+        }
     }
 }
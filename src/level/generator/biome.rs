@@ -0,0 +1,206 @@
+use crate::block::Block;
+
+use super::structure::StructureSpawn;
+
+/// How far past the closest biome in climate another biome can be and still blend into it.
+/// The larger this is, the wider the transitions between biomes.
+const BLEND_WIDTH: f64 = 0.1;
+
+/// The climate of a column, with both values between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climate {
+    pub temperature: f64,
+    pub moisture: f64,
+}
+
+impl Climate {
+    pub const fn new(temperature: f64, moisture: f64) -> Self {
+        Self {
+            temperature,
+            moisture,
+        }
+    }
+
+    fn distance(self, other: Self) -> f64 {
+        let dt = self.temperature - other.temperature;
+        let dm = self.moisture - other.moisture;
+        (dt * dt + dm * dm).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    pub name: &'static str,
+    /// The climate the biome is found in. Each column belongs to the biome with the closest
+    /// climate, and blends into the biomes with climates nearly as close.
+    pub climate: Climate,
+    pub surface: Block,
+    /// A block scattered in patches over the surface, if any.
+    pub surface_patches: Option<Block>,
+    pub subsurface: Block,
+    /// How many blocks of subsurface there are under the surface, before rock.
+    pub subsurface_depth: i32,
+    /// The height the terrain varies around.
+    pub base_height: f64,
+    /// How far the terrain rises above and falls below its base height.
+    pub height_amplitude: f64,
+    /// The structures placed on the biome's terrain, like trees and boulders.
+    pub structures: Vec<StructureSpawn>,
+}
+
+/// The biomes a world is made of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeRegistry {
+    biomes: Vec<Biome>,
+}
+
+impl Default for BiomeRegistry {
+    fn default() -> Self {
+        Self::new(vec![
+            Biome {
+                name: "Plains",
+                climate: Climate::new(0.55, 0.45),
+                surface: Block::Grass,
+                surface_patches: Some(Block::Gravel),
+                subsurface: Block::Dirt,
+                subsurface_depth: 2,
+                base_height: 4.0,
+                height_amplitude: 6.0,
+                structures: vec![
+                    StructureSpawn::new("oak", 12, 0.12),
+                    StructureSpawn::new("bush", 12, 0.25),
+                    StructureSpawn::new("boulder", 28, 0.25),
+                ],
+            },
+            Biome {
+                name: "Forest",
+                climate: Climate::new(0.45, 0.75),
+                surface: Block::Grass,
+                surface_patches: None,
+                subsurface: Block::Dirt,
+                subsurface_depth: 3,
+                base_height: 6.0,
+                height_amplitude: 12.0,
+                structures: vec![
+                    StructureSpawn::new("oak", 6, 0.3),
+                    StructureSpawn::new("birch", 6, 0.2),
+                    StructureSpawn::new("bush", 6, 0.1),
+                    StructureSpawn::new("fallen_log", 20, 0.3),
+                ],
+            },
+            Biome {
+                name: "Desert",
+                climate: Climate::new(0.85, 0.2),
+                surface: Block::Sand,
+                surface_patches: None,
+                subsurface: Block::Sand,
+                subsurface_depth: 4,
+                base_height: 3.0,
+                height_amplitude: 4.0,
+                structures: vec![StructureSpawn::new("boulder", 24, 0.15)],
+            },
+            Biome {
+                name: "Hills",
+                climate: Climate::new(0.3, 0.5),
+                surface: Block::Grass,
+                surface_patches: Some(Block::Gravel),
+                subsurface: Block::Dirt,
+                subsurface_depth: 2,
+                base_height: 10.0,
+                height_amplitude: 24.0,
+                structures: vec![
+                    StructureSpawn::new("pine", 10, 0.12),
+                    StructureSpawn::new("oak", 10, 0.05),
+                    StructureSpawn::new("bush", 10, 0.1),
+                    StructureSpawn::new("boulder", 20, 0.3),
+                ],
+            },
+            Biome {
+                name: "Mountains",
+                climate: Climate::new(0.1, 0.25),
+                surface: Block::Gravel,
+                surface_patches: Some(Block::Rock),
+                subsurface: Block::Rock,
+                subsurface_depth: 0,
+                base_height: 28.0,
+                height_amplitude: 40.0,
+                structures: vec![
+                    StructureSpawn::new("pine", 12, 0.08),
+                    StructureSpawn::new("boulder", 16, 0.3),
+                ],
+            },
+        ])
+    }
+}
+
+impl BiomeRegistry {
+    /// # Panics
+    ///
+    /// If there are no biomes.
+    pub fn new(biomes: Vec<Biome>) -> Self {
+        assert!(!biomes.is_empty(), "a world needs at least one biome");
+        Self { biomes }
+    }
+
+    pub fn biomes(&self) -> &[Biome] {
+        &self.biomes
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Biome> {
+        self.biomes.iter().find(|biome| biome.name == name)
+    }
+
+    /// Finds the biome for a climate, along with its terrain blended with the biomes around
+    /// it, so that terrain changes smoothly across biome borders instead of forming cliffs.
+    pub fn sample(&self, climate: Climate) -> BiomeSample<'_> {
+        let distances = || {
+            self.biomes
+                .iter()
+                .map(move |biome| (biome, biome.climate.distance(climate)))
+        };
+
+        let (biome, closest) = distances()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("a world needs at least one biome");
+
+        let mut total = 0.0;
+        let mut base_height = 0.0;
+        let mut height_amplitude = 0.0;
+
+        for (other, distance) in distances() {
+            let weight = smoothstep(1.0 - (distance - closest) / BLEND_WIDTH);
+
+            if weight <= 0.0 {
+                continue;
+            }
+
+            total += weight;
+            base_height += other.base_height * weight;
+            height_amplitude += other.height_amplitude * weight;
+        }
+
+        // The closest biome always has a weight of 1, so the total is never 0
+        BiomeSample {
+            biome,
+            blend: 1.0 - 1.0 / total,
+            base_height: base_height / total,
+            height_amplitude: height_amplitude / total,
+        }
+    }
+}
+
+/// The biome at a column, with the parts of its terrain that blend with other biomes.
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeSample<'a> {
+    pub biome: &'a Biome,
+    /// How much of the terrain comes from other biomes, from 0 in the middle of a biome to
+    /// 0.5 right on its border.
+    pub blend: f64,
+    pub base_height: f64,
+    pub height_amplitude: f64,
+}
+
+pub(super) fn smoothstep(x: f64) -> f64 {
+    let x = x.clamp(0.0, 1.0);
+    x * x * (3.0 - 2.0 * x)
+}
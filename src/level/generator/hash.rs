@@ -0,0 +1,52 @@
+/// Added before each mix, so that runs of zeros still come out scrambled.
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// SplitMix64's finalizer, which scrambles every bit of its input into every bit of its output.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Hashes a seed and a list of integers, such as a position, into 64 random-looking bits.
+/// All of the generator's randomness comes from this rather than from
+/// [`std::hash::DefaultHasher`] or a random number generator, neither of which promise the same
+/// output across Rust or crate versions. It works out as:
+///
+/// ```text
+/// hash = mix(seed + GOLDEN_GAMMA)
+/// for each value:
+///     hash = mix((hash + GOLDEN_GAMMA) ^ (value as u32))
+/// ```
+///
+/// with wrapping 64-bit arithmetic, where `mix` is SplitMix64's finalizer. Changing any of this
+/// moves everything in every existing world.
+pub fn stable_hash(seed: u64, values: &[i32]) -> u64 {
+    values
+        .iter()
+        .fold(mix(seed.wrapping_add(GOLDEN_GAMMA)), |hash, &value| {
+            mix(hash.wrapping_add(GOLDEN_GAMMA) ^ u64::from(value as u32))
+        })
+}
+
+/// A number from 0 up to but not including 1, from the top 24 bits of a hash.
+pub fn unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1 << 24) as f32
+}
+
+/// A number from 0 up to but not including a bound, which has to be positive.
+pub fn below(hash: u64, bound: i32) -> i32 {
+    (hash % bound as u64) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_pinned() {
+        assert_eq!(stable_hash(0, &[]), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(stable_hash(42, &[1, -2, 3]), 0x675c_0d9b_4008_d392);
+        assert_ne!(stable_hash(42, &[1, 2]), stable_hash(42, &[2, 1]));
+    }
+}
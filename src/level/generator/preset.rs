@@ -0,0 +1,324 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::position::CHUNK_SIZE;
+
+use super::{
+    biome::BiomeRegistry,
+    structure::{Canopy, StructureRegistry, Tree},
+    water::DEFAULT_SEA_LEVEL,
+};
+
+pub const DEFAULT_PRESET: &str = "default";
+
+/// The tallest trunk and widest canopy a tree can have, in blocks. Much bigger trees would
+/// overflow the positions they're placed at, and take ages to place.
+const MAX_TREE_SIZE: i32 = 4 * CHUNK_SIZE as i32;
+
+/// The presets that come with the game, by name. Each one only lists what it changes from
+/// the default preset.
+const BUILTIN_PRESETS: [(&str, &str); 3] = [
+    ("flat", include_str!("../../../assets/presets/flat.ron")),
+    (
+        "amplified",
+        include_str!("../../../assets/presets/amplified.ron"),
+    ),
+    (
+        "archipelago",
+        include_str!("../../../assets/presets/archipelago.ron"),
+    ),
+];
+
+/// Tuning for the world generator, written in RON. A world stores the preset it was created
+/// with, so that it keeps generating the same terrain even if the preset file changes later.
+/// Anything a preset leaves out is taken from the default preset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldPreset {
+    pub sea_level: i32,
+    pub lakes: bool,
+    pub rivers: bool,
+    /// How many blocks across climate changes over. Biomes are roughly this size.
+    pub climate_scale: f64,
+    /// Spreads climate noise out, which otherwise rarely strays far from the middle, so that
+    /// biomes with more extreme climates still show up.
+    pub climate_contrast: f64,
+    /// The layers of noise the terrain's shape is made of, before each biome scales it.
+    pub terrain_octaves: Vec<Octave>,
+    /// Scales how high every biome's terrain is and how far it rises and falls.
+    pub height_scale: f64,
+    /// Moves every biome's terrain up or down, after it's scaled.
+    pub height_offset: f64,
+    /// How many blocks across the noise that bends terrain into cliffs and overhangs varies over.
+    pub overhang_scale: f64,
+    /// How far terrain can be pushed in or out by that noise, as a fraction of its biome's
+    /// height amplitude.
+    pub overhang_factor: f64,
+    pub caves: bool,
+    /// How many times the noise for patches of a biome's other surface block varies per block.
+    pub surface_patch_frequency: f64,
+    /// Patches are where their noise is above this, so higher values mean fewer patches.
+    pub surface_patch_threshold: f64,
+    /// How far above the sea the ground is still beach.
+    pub beach_height: i32,
+    /// Beaches and the ground under water are at least this deep.
+    pub shore_depth: i32,
+    /// How far under water the ground turns from sand to gravel.
+    pub deep_water: i32,
+    /// Scales how far apart biomes place structures, so higher values mean fewer of them.
+    pub structure_spacing: f64,
+    /// The trees biomes can grow, by name.
+    pub trees: BTreeMap<String, Tree>,
+}
+
+/// A layer of terrain noise.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Octave {
+    /// How many times the noise varies per block.
+    pub frequency: f64,
+    /// How much the layer adds to the terrain's shape.
+    pub amplitude: f64,
+}
+
+impl Default for WorldPreset {
+    fn default() -> Self {
+        Self {
+            sea_level: DEFAULT_SEA_LEVEL,
+            lakes: true,
+            rivers: true,
+            climate_scale: 400.0,
+            climate_contrast: 1.6,
+            terrain_octaves: vec![
+                Octave {
+                    frequency: 0.01,
+                    amplitude: 1.0,
+                },
+                Octave {
+                    frequency: 0.05,
+                    amplitude: 0.3,
+                },
+                Octave {
+                    frequency: 0.1,
+                    amplitude: 0.1,
+                },
+            ],
+            height_scale: 1.0,
+            height_offset: 0.0,
+            overhang_scale: 16.0,
+            overhang_factor: 0.3,
+            caves: true,
+            surface_patch_frequency: 0.08,
+            surface_patch_threshold: 0.6,
+            beach_height: 2,
+            shore_depth: 3,
+            deep_water: 8,
+            structure_spacing: 1.0,
+            trees: BTreeMap::from([
+                (
+                    "oak".to_string(),
+                    Tree {
+                        heights: 5..=7,
+                        canopy: Canopy::Round { radius: 3 },
+                    },
+                ),
+                (
+                    "birch".to_string(),
+                    Tree {
+                        heights: 7..=9,
+                        canopy: Canopy::Round { radius: 2 },
+                    },
+                ),
+                (
+                    "pine".to_string(),
+                    Tree {
+                        heights: 8..=11,
+                        canopy: Canopy::Conical { radius: 3 },
+                    },
+                ),
+            ]),
+        }
+    }
+}
+
+impl WorldPreset {
+    /// The names of the presets that come with the game.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        [DEFAULT_PRESET]
+            .into_iter()
+            .chain(BUILTIN_PRESETS.iter().map(|(name, _)| *name))
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        if name == DEFAULT_PRESET {
+            return Some(Self::default());
+        }
+
+        let (_, source) = BUILTIN_PRESETS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)?;
+
+        // Built-in presets are checked by the tests, so they always parse
+        Some(Self::from_ron(source).expect("built-in presets are valid"))
+    }
+
+    /// Loads a built-in preset by name, or otherwise a preset file by its path.
+    pub fn load(name: &str) -> Result<Self, String> {
+        if let Some(preset) = Self::builtin(name) {
+            return Ok(preset);
+        }
+
+        let path = Path::new(name);
+
+        if !path.is_file() {
+            let builtin: Vec<_> = Self::builtin_names().collect();
+            return Err(format!(
+                "`{name}` is neither a preset file nor one of {}",
+                builtin.join(", ")
+            ));
+        }
+
+        let source = fs::read_to_string(path)
+            .map_err(|error| format!("failed to read {}: {error}", path.display()))?;
+
+        Self::from_ron(&source).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, String> {
+        let preset: Self = ron::from_str(source).map_err(|error| error.to_string())?;
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .expect("presets can always be serialized")
+    }
+
+    /// Checks for settings the generator can't work with.
+    fn validate(&self) -> Result<(), String> {
+        if self.terrain_octaves.is_empty() {
+            return Err("terrain needs at least one octave".to_string());
+        }
+
+        if self.climate_scale <= 0.0 || self.overhang_scale <= 0.0 {
+            return Err("noise scales need to be positive".to_string());
+        }
+
+        if self.overhang_factor < 0.0 {
+            return Err("overhang factor can't be negative".to_string());
+        }
+
+        if self.structure_spacing <= 0.0 {
+            return Err("structure spacing needs to be positive".to_string());
+        }
+
+        for (name, tree) in &self.trees {
+            if tree.heights.is_empty() || *tree.heights.start() < 1 {
+                return Err(format!("tree `{name}` needs heights of at least 1"));
+            }
+
+            let (Canopy::Round { radius } | Canopy::Conical { radius }) = tree.canopy;
+
+            if radius < 0 {
+                return Err(format!("tree `{name}` can't have a negative canopy radius"));
+            }
+
+            if *tree.heights.end() > MAX_TREE_SIZE || radius > MAX_TREE_SIZE {
+                return Err(format!(
+                    "tree `{name}` can't be taller or have a wider canopy than {MAX_TREE_SIZE}"
+                ));
+            }
+        }
+
+        let mut structures = StructureRegistry::default();
+        self.register_trees(&mut structures);
+
+        let spawns = BiomeRegistry::default()
+            .biomes()
+            .iter()
+            .flat_map(|biome| biome.structures.clone())
+            .collect::<Vec<_>>();
+
+        match spawns
+            .iter()
+            .find(|spawn| structures.get(spawn.structure).is_none())
+        {
+            Some(spawn) => Err(format!(
+                "biomes grow `{}`, which is missing",
+                spawn.structure
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds the preset's trees to a registry, replacing any with the same name.
+    pub(super) fn register_trees(&self, structures: &mut StructureRegistry) {
+        for (name, tree) in &self.trees {
+            structures.register(name.clone(), tree.clone());
+        }
+    }
+
+    /// Scales a spacing biomes place structures at, keeping it at least a block.
+    pub(super) fn scale_spacing(&self, spacing: i32) -> i32 {
+        ((spacing as f64 * self.structure_spacing).round() as i32).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_presets_parse() {
+        for name in WorldPreset::builtin_names() {
+            let preset = WorldPreset::builtin(name).unwrap();
+            assert_eq!(
+                WorldPreset::from_ron(&preset.to_ron()),
+                Ok(preset),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_settings_are_default() {
+        let preset = WorldPreset::from_ron("(sea_level: 12)").unwrap();
+
+        assert_eq!(preset.sea_level, 12);
+        assert_eq!(preset.trees, WorldPreset::default().trees);
+    }
+
+    #[test]
+    fn missing_trees_are_rejected() {
+        assert!(WorldPreset::from_ron("(trees: {})").is_err());
+    }
+
+    #[test]
+    fn negative_sizes_are_rejected() {
+        assert!(WorldPreset::from_ron("(overhang_factor: -0.5)").is_err());
+
+        let mut preset = WorldPreset::default();
+        preset.trees.get_mut("oak").unwrap().canopy = Canopy::Round { radius: -1 };
+        assert!(WorldPreset::from_ron(&preset.to_ron()).is_err());
+
+        preset.trees.get_mut("oak").unwrap().canopy = Canopy::Conical { radius: -2 };
+        assert!(WorldPreset::from_ron(&preset.to_ron()).is_err());
+    }
+
+    #[test]
+    fn huge_trees_are_rejected() {
+        let mut preset = WorldPreset::default();
+        preset.trees.get_mut("oak").unwrap().heights = 4..=i32::MAX;
+        assert!(WorldPreset::from_ron(&preset.to_ron()).is_err());
+
+        let mut preset = WorldPreset::default();
+        preset.trees.get_mut("oak").unwrap().canopy = Canopy::Round { radius: 1000 };
+        assert!(WorldPreset::from_ron(&preset.to_ron()).is_err());
+
+        let mut preset = WorldPreset::default();
+        preset.trees.get_mut("oak").unwrap().heights = 4..=MAX_TREE_SIZE;
+        assert!(WorldPreset::from_ron(&preset.to_ron()).is_ok());
+    }
+}
@@ -0,0 +1,253 @@
+use std::{fmt, ops::RangeInclusive, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{block::Block, position::BlockPos};
+
+use super::hash::{stable_hash, unit};
+
+/// Something placed on top of the terrain, like a tree or a boulder. A structure can cross
+/// chunk borders, and each chunk it overlaps asks it for its own blocks, so the blocks it
+/// gives must only depend on where they are and the seed it was placed with.
+pub trait Structure: fmt::Debug + Send + Sync {
+    /// The corners of the box every block of the structure fits in, relative to its origin,
+    /// which is the block just above the ground it stands on.
+    fn bounds(&self) -> (BlockPos, BlockPos);
+
+    /// The block at an offset from the structure's origin, if it has one there.
+    fn block(&self, offset: BlockPos, seed: u64) -> Option<Block>;
+
+    /// Whether the structure can stand on a block.
+    fn can_stand_on(&self, ground: Block) -> bool {
+        ground.supports_plants()
+    }
+}
+
+/// How often a biome places a structure. Structures are attempted once in every square cell
+/// of `spacing` blocks, at a random spot inside it. Each of a biome's spawns with the same
+/// spacing shares those attempts, and is picked by `weight`, between 0 and 1, with nothing
+/// placed the rest of the time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureSpawn {
+    pub structure: &'static str,
+    pub spacing: i32,
+    pub weight: f32,
+}
+
+impl StructureSpawn {
+    pub const fn new(structure: &'static str, spacing: i32, weight: f32) -> Self {
+        Self {
+            structure,
+            spacing,
+            weight,
+        }
+    }
+}
+
+/// The structures biomes can place, by name.
+#[derive(Debug, Clone)]
+pub struct StructureRegistry {
+    structures: Vec<(String, Arc<dyn Structure>)>,
+}
+
+impl Default for StructureRegistry {
+    /// Every structure except trees, which come from the world's preset.
+    fn default() -> Self {
+        let mut registry = Self::new();
+
+        registry.register("bush", Bush);
+        registry.register("boulder", Boulder);
+        registry.register("fallen_log", FallenLog);
+
+        registry
+    }
+}
+
+impl StructureRegistry {
+    pub fn new() -> Self {
+        Self {
+            structures: Vec::new(),
+        }
+    }
+
+    /// Adds a structure, replacing any with the same name.
+    pub fn register(&mut self, name: impl Into<String>, structure: impl Structure + 'static) {
+        let name = name.into();
+        self.structures.retain(|(existing, _)| *existing != name);
+        self.structures.push((name, Arc::new(structure)));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Structure> {
+        self.structures
+            .iter()
+            .find(|(existing, _)| *existing == name)
+            .map(|(_, structure)| structure.as_ref())
+    }
+
+    /// The corners of the box every registered structure fits in.
+    pub fn bounds(&self) -> (BlockPos, BlockPos) {
+        let zero = BlockPos::new(0, 0, 0);
+
+        self.structures
+            .iter()
+            .map(|(_, structure)| structure.bounds())
+            .fold((zero, zero), |(min, max), (low, high)| {
+                (
+                    BlockPos::new(min.x.min(low.x), min.y.min(low.y), min.z.min(low.z)),
+                    BlockPos::new(max.x.max(high.x), max.y.max(high.y), max.z.max(high.z)),
+                )
+            })
+    }
+}
+
+/// A number between 0 and 1 picked by a position in a structure, so that the same block
+/// of the same structure always comes out the same.
+fn roll(seed: u64, offset: BlockPos) -> f32 {
+    unit(stable_hash(seed, &[offset.x, offset.y, offset.z]))
+}
+
+/// Picks one of a range of sizes for a placement.
+fn pick(seed: u64, sizes: &RangeInclusive<i32>) -> i32 {
+    let count = (sizes.end() - sizes.start() + 1).max(1) as u64;
+    sizes.start() + (seed % count) as i32
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tree {
+    /// How tall the trunk can be.
+    pub heights: RangeInclusive<i32>,
+    pub canopy: Canopy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Canopy {
+    /// A rounded crown around the top of the trunk.
+    Round { radius: i32 },
+    /// Layers of leaves narrowing all the way up the trunk, like a conifer.
+    Conical { radius: i32 },
+}
+
+impl Structure for Tree {
+    fn bounds(&self) -> (BlockPos, BlockPos) {
+        let radius = match self.canopy {
+            Canopy::Round { radius } | Canopy::Conical { radius } => radius,
+        };
+
+        (
+            BlockPos::new(-radius, 0, -radius),
+            BlockPos::new(radius, *self.heights.end(), radius),
+        )
+    }
+
+    fn block(&self, offset: BlockPos, seed: u64) -> Option<Block> {
+        let height = pick(seed, &self.heights);
+
+        if offset.x == 0 && offset.z == 0 && offset.y >= 0 && offset.y < height {
+            return Some(Block::Wood);
+        }
+
+        let distance_sq = offset.x * offset.x + offset.z * offset.z;
+
+        let (radius, trimmed) = match self.canopy {
+            Canopy::Round { radius } => {
+                let leaf_start = height - 4;
+                let layer = offset.y - leaf_start;
+
+                if !(0..=4).contains(&layer) {
+                    return None;
+                }
+
+                let radius = if layer == 0 || layer == 4 {
+                    radius - 1
+                } else {
+                    radius
+                };
+
+                // Leaves around the edge are thinned out, but none of the corners are kept
+                let edge = offset.x.abs() == radius || offset.z.abs() == radius;
+                (radius, edge && roll(seed, offset) < 0.5)
+            }
+            Canopy::Conical { radius } => {
+                if offset.y < 2 || offset.y > height {
+                    return None;
+                }
+
+                // Narrows towards the top, with every other layer pulled in
+                let progress = (height - offset.y) as f32 / (height - 2).max(1) as f32;
+                let radius = (radius as f32 * progress).ceil() as i32 - (offset.y % 2);
+                (radius.max(0), false)
+            }
+        };
+
+        let inside = offset.x.abs() <= radius
+            && offset.z.abs() <= radius
+            && distance_sq <= radius * radius + 1;
+
+        (inside && !trimmed).then_some(Block::Leaves)
+    }
+}
+
+/// A low clump of leaves.
+#[derive(Debug, Clone, Copy)]
+pub struct Bush;
+
+impl Structure for Bush {
+    fn bounds(&self) -> (BlockPos, BlockPos) {
+        (BlockPos::new(-1, 0, -1), BlockPos::new(1, 1, 1))
+    }
+
+    fn block(&self, offset: BlockPos, seed: u64) -> Option<Block> {
+        let spread = offset.x.abs() + offset.z.abs();
+
+        let leaf = match offset.y {
+            0 => spread < 2 || roll(seed, offset) < 0.4,
+            1 => spread == 0 || (spread == 1 && roll(seed, offset) < 0.5),
+            _ => false,
+        };
+
+        leaf.then_some(Block::Leaves)
+    }
+}
+
+/// A lump of rock sitting on the ground. Its bottom layer is below the surface, but since
+/// structures never replace terrain it only fills in where the ground slopes away.
+#[derive(Debug, Clone, Copy)]
+pub struct Boulder;
+
+impl Structure for Boulder {
+    fn bounds(&self) -> (BlockPos, BlockPos) {
+        (BlockPos::new(-2, -1, -2), BlockPos::new(2, 2, 2))
+    }
+
+    fn block(&self, offset: BlockPos, seed: u64) -> Option<Block> {
+        let radius = pick(seed, &(1..=2));
+        let distance_sq = offset.x * offset.x + offset.y * offset.y + offset.z * offset.z;
+
+        (distance_sq < radius * radius + radius).then_some(Block::Rock)
+    }
+
+    fn can_stand_on(&self, ground: Block) -> bool {
+        ground.is_solid()
+    }
+}
+
+/// A tree trunk lying on the ground, along either the x or z axis.
+#[derive(Debug, Clone, Copy)]
+pub struct FallenLog;
+
+impl Structure for FallenLog {
+    fn bounds(&self) -> (BlockPos, BlockPos) {
+        (BlockPos::new(0, 0, 0), BlockPos::new(4, 0, 4))
+    }
+
+    fn block(&self, offset: BlockPos, seed: u64) -> Option<Block> {
+        let length = pick(seed, &(3..=5));
+        let (along, across) = if seed & (1 << 32) == 0 {
+            (offset.x, offset.z)
+        } else {
+            (offset.z, offset.x)
+        };
+
+        (offset.y == 0 && across == 0 && (0..length).contains(&along)).then_some(Block::Wood)
+    }
+}
@@ -0,0 +1,125 @@
+use noise::{NoiseFn, Perlin};
+
+use super::{
+    biome::{smoothstep, BiomeSample},
+    preset::WorldPreset,
+};
+
+pub const DEFAULT_SEA_LEVEL: i32 = 0;
+
+/// How many blocks across lakes are spread out over.
+const LAKE_SCALE: f64 = 160.0;
+/// Lakes are where the lake noise is above this.
+const LAKE_THRESHOLD: f64 = 0.45;
+/// How far below the threshold lakes have banks raised up to their water level. Nothing
+/// outside a lake knows how high its water is, so without them the water could end in a
+/// wall wherever the terrain around it is lower.
+const LAKE_RIM: f64 = 0.4;
+const LAKE_DEPTH: f64 = 6.0;
+/// How far above the sea the land needs to be for a lake, so that lakes never spill into it.
+const LAKE_MIN_ELEVATION: f64 = 3.0;
+
+/// How many blocks across rivers wind over.
+const RIVER_SCALE: f64 = 300.0;
+/// How far from the middle of a river, in river noise, its valley reaches.
+const RIVER_VALLEY_WIDTH: f64 = 0.06;
+/// How far below sea level river beds are carved.
+const RIVER_DEPTH: f64 = 3.0;
+
+/// Shapes terrain around the sea, lakes and rivers. Rivers are valleys carved down below sea
+/// level, along the lines where the river noise crosses zero, so the sea floods them too.
+#[derive(Debug, Default, Clone)]
+pub(super) struct Waterways {
+    lake_noise: Perlin,
+    river_noise: Perlin,
+    sea_level: i32,
+    lakes: bool,
+    rivers: bool,
+}
+
+/// A column of terrain after water has shaped it.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Shore {
+    pub height: f64,
+    /// How high water fills the column, if it has any.
+    pub water_level: Option<i32>,
+    /// How much the column is part of a lake bed, a lake's bank or a river valley, from 0 to 1.
+    /// Terrain there is kept from overhanging, so that it doesn't open holes next to water.
+    pub bank: f64,
+}
+
+impl Waterways {
+    pub fn new(seed: u32, preset: &WorldPreset) -> Self {
+        Self {
+            lake_noise: Perlin::new(seed),
+            river_noise: Perlin::new(seed + 1),
+            sea_level: preset.sea_level,
+            lakes: preset.lakes,
+            rivers: preset.rivers,
+        }
+    }
+
+    pub fn sea_level(&self) -> i32 {
+        self.sea_level
+    }
+
+    pub fn shape(&self, x: i32, z: i32, height: f64, biome: &BiomeSample) -> Shore {
+        let sea_level = self.sea_level as f64;
+        // Without rivers, every column is infinitely far from one
+        let river = if self.rivers {
+            self.river_noise
+                .get([x as f64 / RIVER_SCALE, z as f64 / RIVER_SCALE])
+                .abs()
+        } else {
+            f64::INFINITY
+        };
+
+        // A lake's water is as high as its biome's terrain is on average. Lakes fade out near
+        // rivers and towards biome borders, which they'd otherwise spill over, and stay out of
+        // biomes too low to hold them without spilling into the sea
+        let lake_level = biome.biome.base_height.round();
+        let near_river = 2.0 * (1.0 - river / (2.0 * RIVER_VALLEY_WIDTH)).max(0.0);
+        let border = 2.0 * biome.blend;
+
+        let lake = if self.lakes && lake_level >= sea_level + LAKE_MIN_ELEVATION {
+            self.lake_noise
+                .get([x as f64 / LAKE_SCALE, z as f64 / LAKE_SCALE])
+                - LAKE_THRESHOLD
+                - near_river
+                - border
+        } else {
+            -1.0
+        };
+
+        let mut shore = if lake > 0.0 {
+            let depth = 1.0 + LAKE_DEPTH * (lake / (1.0 - LAKE_THRESHOLD)).min(1.0).sqrt();
+
+            Shore {
+                height: height.min(lake_level - depth),
+                water_level: Some(lake_level as i32),
+                bank: 1.0,
+            }
+        } else {
+            // Banks reach all the way up to the water well before the lake starts
+            let bank = smoothstep(2.0 * (1.0 + lake / LAKE_RIM));
+
+            Shore {
+                height: height + (height.max(lake_level) - height) * bank,
+                water_level: None,
+                bank,
+            }
+        };
+
+        let valley = smoothstep(1.0 - river / RIVER_VALLEY_WIDTH);
+        let river_bed = sea_level - RIVER_DEPTH;
+
+        shore.height += (river_bed - shore.height) * valley;
+        shore.bank = shore.bank.max(valley);
+
+        if shore.water_level.is_none() && shore.height.round() < sea_level {
+            shore.water_level = Some(self.sea_level);
+        }
+
+        shore
+    }
+}
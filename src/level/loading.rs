@@ -0,0 +1,810 @@
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_tokio_tasks::{TaskContext, TokioTasksRuntime};
+use sqlx::SqlitePool;
+use tokio::{
+    task::{self, AbortHandle, Id, JoinError, JoinSet},
+    time::{sleep, timeout},
+};
+
+use crate::{
+    chunk::Chunk,
+    player::{Player, PlayerCamera},
+    position::{BlockPos, ChunkPos},
+};
+
+use super::{
+    cache::ChunkCache,
+    format::{decode_chunk, decode_chunk_delta, encode_chunk},
+    generator::{LevelGenerator, GENERATOR_VERSION},
+    persistence::{quarantine_chunk, report, retry, PersistenceError},
+    with_level,
+    world::{StorageMode, WorldMetadata},
+    ChunkGenerationQueue, Level, LevelDatabase, LoadedChunk, Modified, RenderDistance,
+};
+
+/// How long to wait for chunks to finish loading before checking what to load next.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to wait when there's nothing to load.
+const IDLE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct ChunkLoadingSettings {
+    /// The most chunks that can be loading or generating at the same time.
+    pub max_in_flight: usize,
+}
+
+impl Default for ChunkLoadingSettings {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+
+        Self {
+            max_in_flight: threads * 2,
+        }
+    }
+}
+
+/// How chunk loading is keeping up with the player.
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct ChunkLoadingStats {
+    pub chunks_per_second: f32,
+    /// Chunks within the render distance that haven't started loading yet.
+    pub queue_depth: usize,
+    pub in_flight: usize,
+    window_start: f32,
+    loaded_in_window: usize,
+}
+
+impl ChunkLoadingStats {
+    fn record_loaded(&mut self, count: usize, now: f32) {
+        self.loaded_in_window += count;
+
+        let elapsed = now - self.window_start;
+
+        if elapsed >= 1.0 {
+            self.chunks_per_second = self.loaded_in_window as f32 / elapsed;
+            self.loaded_in_window = 0;
+            self.window_start = now;
+        }
+    }
+}
+
+/// What the loading task should do next, decided on the main thread.
+struct LoadPlan {
+    epoch: u64,
+    start: Vec<ChunkPos>,
+    cancel: Vec<ChunkPos>,
+}
+
+type LoadResult = (ChunkPos, Result<(Chunk, bool), PersistenceError>);
+
+/// A chunk that's being loaded on its own task.
+struct LoadJob {
+    handle: AbortHandle,
+    /// Checked before generating, since generation runs on the blocking thread pool where
+    /// aborting the task can't stop it.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LoadJob {
+    fn cancel(self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.handle.abort();
+    }
+}
+
+/// Loads chunks around the player on a pool of tasks. Each chunk is read from the database on
+/// its own task, and generated on tokio's blocking thread pool, with at most
+/// [`ChunkLoadingSettings::max_in_flight`] at a time. Chunks that go out of range before
+/// they've finished loading are cancelled.
+pub(super) fn start_chunk_generation(
+    db: Res<LevelDatabase>,
+    runtime: Res<TokioTasksRuntime>,
+    generator: Res<LevelGenerator>,
+    metadata: Res<WorldMetadata>,
+) {
+    let db = db.0.clone();
+    let generator = Arc::new(generator.clone());
+    let storage = metadata.storage;
+
+    runtime.spawn_background_task(move |mut ctx| async move {
+        let mut jobs: JoinSet<LoadResult> = JoinSet::new();
+        let mut in_flight: HashMap<ChunkPos, LoadJob> = HashMap::new();
+        let mut epoch = 0;
+        let mut finished: Vec<Result<(Id, LoadResult), JoinError>> = Vec::new();
+
+        loop {
+            let mut loaded = Vec::new();
+            let mut failed = Vec::new();
+            let mut panicked = Vec::new();
+
+            for result in finished.drain(..) {
+                let (id, (pos, result)) = match result {
+                    Ok(finished) => finished,
+                    Err(error) => {
+                        // Cancelled jobs have already been forgotten, but a job that panicked
+                        // never got to say which chunk it was loading
+                        let Some(pos) = in_flight
+                            .iter()
+                            .find(|(_, job)| job.handle.id() == error.id())
+                            .map(|(&pos, _)| pos)
+                        else {
+                            continue;
+                        };
+
+                        in_flight.remove(&pos);
+
+                        let error = PersistenceError::ChunkPanicked {
+                            pos,
+                            error: panic_message(error),
+                        };
+
+                        report(&mut ctx, error).await;
+                        panicked.push(pos);
+                        continue;
+                    }
+                };
+
+                // Ignore chunks that were cancelled after they'd already finished
+                if in_flight.get(&pos).map(|job| job.handle.id()) != Some(id) {
+                    continue;
+                }
+
+                in_flight.remove(&pos);
+
+                match result {
+                    Ok(chunk) => loaded.push((pos, chunk)),
+                    Err(error @ PersistenceError::ChunkPanicked { .. }) => {
+                        report(&mut ctx, error).await;
+                        panicked.push(pos);
+                    }
+                    Err(error) => {
+                        report(&mut ctx, error).await;
+                        failed.push(pos);
+                    }
+                }
+            }
+
+            let in_flight_count = in_flight.len();
+
+            let plan = ctx
+                .run_on_main_thread(move |ctx| {
+                    update_chunk_loading(
+                        ctx.world,
+                        epoch,
+                        loaded,
+                        failed,
+                        panicked,
+                        in_flight_count,
+                    )
+                })
+                .await;
+
+            // The level was reset, so everything that was loading is out of date
+            if plan.epoch != epoch {
+                for (_, job) in in_flight.drain() {
+                    job.cancel();
+                }
+
+                epoch = plan.epoch;
+            }
+
+            for pos in plan.cancel {
+                if let Some(job) = in_flight.remove(&pos) {
+                    job.cancel();
+                }
+            }
+
+            for pos in plan.start {
+                let mut ctx = ctx.clone();
+                let db = db.clone();
+                let generator = Generation {
+                    generator: generator.clone(),
+                    cancelled: Arc::new(AtomicBool::new(false)),
+                };
+                let cancelled = generator.cancelled.clone();
+
+                let handle = jobs.spawn(async move {
+                    let loaded = match storage {
+                        StorageMode::Full => load_chunk(&mut ctx, &db, &generator, pos).await,
+                        StorageMode::Delta => {
+                            load_chunk_delta(&mut ctx, &db, &generator, pos).await
+                        }
+                    };
+
+                    (pos, loaded)
+                });
+
+                in_flight.insert(pos, LoadJob { handle, cancelled });
+            }
+
+            if jobs.is_empty() {
+                sleep(IDLE_INTERVAL).await;
+                continue;
+            }
+
+            // Wait for something to finish, then take everything else that's done too
+            if let Ok(Some(result)) = timeout(POLL_INTERVAL, jobs.join_next_with_id()).await {
+                finished.push(result);
+            }
+
+            while let Some(result) = jobs.try_join_next_with_id() {
+                finished.push(result);
+            }
+        }
+    });
+}
+
+/// Adds chunks that finished loading to the level, cancels chunks that are no longer in
+/// range, and picks which chunks to start loading next.
+fn update_chunk_loading(
+    world: &mut World,
+    epoch: u64,
+    loaded: Vec<(ChunkPos, (Chunk, bool))>,
+    failed: Vec<ChunkPos>,
+    panicked: Vec<ChunkPos>,
+    in_flight: usize,
+) -> LoadPlan {
+    let current_epoch = world.resource::<ChunkGenerationQueue>().epoch;
+    let mut loaded_count = 0;
+
+    // If the level was reset while these were loading, they may be out of date
+    if current_epoch == epoch {
+        let mut queue = world.resource_mut::<ChunkGenerationQueue>();
+
+        // Chunks that failed to load will be tried again on a later pass
+        for pos in failed {
+            queue.pending.remove(&pos);
+        }
+
+        loaded_count += loaded.len();
+
+        for (pos, (chunk, needs_saving)) in loaded {
+            world
+                .resource_mut::<ChunkGenerationQueue>()
+                .pending
+                .remove(&pos);
+
+            insert_loaded_chunk(world, pos, chunk, needs_saving);
+        }
+
+        for pos in panicked {
+            world
+                .resource_mut::<ChunkGenerationQueue>()
+                .pending
+                .remove(&pos);
+
+            insert_panicked_chunk(world, pos);
+        }
+    }
+
+    let mut plan = LoadPlan {
+        epoch: current_epoch,
+        start: Vec::new(),
+        cancel: Vec::new(),
+    };
+
+    let mut player_query = world.query_filtered::<&Transform, With<Player>>();
+    let mut camera_query = world.query_filtered::<&GlobalTransform, With<PlayerCamera>>();
+
+    let Some(player_pos) = player_query
+        .iter(world)
+        .next()
+        .map(|transform| transform.translation)
+    else {
+        return plan;
+    };
+
+    let view_direction = camera_query
+        .iter(world)
+        .next()
+        .map_or(Vec3::ZERO, |transform| *transform.forward());
+
+    let render_distance = *world.resource::<RenderDistance>();
+    let max_in_flight = world.resource::<ChunkLoadingSettings>().max_in_flight;
+    let player_chunk = BlockPos::from_world(player_pos).chunk_pos();
+    // Chunks next to the render distance are loaded too, so that the ones inside it can mesh
+    let radius = render_distance.chunks + 1;
+
+    // Cancel chunks that would be unloaded as soon as they arrived
+    let mut queue = world.resource_mut::<ChunkGenerationQueue>();
+
+    plan.cancel = queue
+        .pending
+        .iter()
+        .copied()
+        .filter(|&pos| render_distance.should_unload(player_chunk, pos))
+        .collect();
+
+    for pos in &plan.cancel {
+        queue.pending.remove(pos);
+    }
+
+    // Find the missing chunks within the render distance
+    let level = world.resource::<Level>();
+    let queue = world.resource::<ChunkGenerationQueue>();
+    let mut missing = Vec::new();
+
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let pos = ChunkPos::new(player_chunk.x + x, player_chunk.y + y, player_chunk.z + z);
+
+                if !render_distance.should_load(player_chunk, pos)
+                    || level.chunk(pos).is_some()
+                    || queue.pending.contains(&pos)
+                    || level.panicked.contains(&pos)
+                {
+                    continue;
+                }
+
+                let priority = render_distance.load_priority(player_pos, view_direction, pos);
+                missing.push((priority, pos));
+            }
+        }
+    }
+
+    // Take the chunks that matter most first
+    missing.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let in_flight = in_flight.saturating_sub(plan.cancel.len());
+    let slots = max_in_flight.saturating_sub(in_flight);
+    let queue_depth = missing.len().saturating_sub(slots);
+
+    for (_, pos) in missing.into_iter().take(slots) {
+        // Recently unloaded chunks can be added straight away
+        if let Some((chunk, dirty)) = world.resource_mut::<ChunkCache>().take(pos) {
+            insert_loaded_chunk(world, pos, chunk, dirty);
+            loaded_count += 1;
+            continue;
+        }
+
+        world
+            .resource_mut::<ChunkGenerationQueue>()
+            .pending
+            .insert(pos);
+
+        plan.start.push(pos);
+    }
+
+    let now = world.resource::<Time>().elapsed_secs();
+    let mut stats = world.resource_mut::<ChunkLoadingStats>();
+    stats.record_loaded(loaded_count, now);
+    stats.queue_depth = queue_depth;
+    stats.in_flight = in_flight + plan.start.len();
+
+    plan
+}
+
+fn insert_loaded_chunk(world: &mut World, chunk_pos: ChunkPos, chunk: Chunk, needs_saving: bool) {
+    world.resource_mut::<Level>().chunks.insert(
+        chunk_pos,
+        LoadedChunk {
+            chunk,
+            entity: None,
+            meshed: false,
+        },
+    );
+
+    with_level(world, |level, commands| {
+        // Write upgraded or unsaved chunks back in the latest format
+        if needs_saving {
+            if let Some(entity) = level.spawn_entity(commands, chunk_pos) {
+                commands.entity(entity).insert(Modified);
+            }
+        }
+
+        // Mesh this chunk and any neighbors that were only waiting for it, each exactly once
+        mark_surrounded_chunks_dirty(level, commands, chunk_pos);
+    });
+}
+
+/// Records a chunk that panicked while generating, so that its neighbors are meshed without it
+/// rather than waiting for it forever. Nothing is stored for it.
+fn insert_panicked_chunk(world: &mut World, chunk_pos: ChunkPos) {
+    with_level(world, |level, commands| {
+        level.panicked.insert(chunk_pos);
+        mark_surrounded_chunks_dirty(level, commands, chunk_pos);
+    });
+}
+
+/// Marks the chunks around a chunk that has just arrived, including itself, for meshing if
+/// they were only waiting for it.
+fn mark_surrounded_chunks_dirty(level: &mut Level, commands: &mut Commands, chunk_pos: ChunkPos) {
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let pos = ChunkPos::new(chunk_pos.x + dx, chunk_pos.y + dy, chunk_pos.z + dz);
+
+                let waiting = level.chunks.get(&pos).is_some_and(|loaded| !loaded.meshed);
+
+                if waiting && level.is_surrounded(pos) {
+                    level.mark_dirty(commands, pos);
+                }
+            }
+        }
+    }
+}
+
+/// The generator for one load job, along with whether that job has been cancelled.
+struct Generation {
+    generator: Arc<LevelGenerator>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Generates a chunk on the blocking thread pool, so that it doesn't hold up other tasks.
+/// Jobs that are cancelled while waiting for a thread are skipped.
+async fn generate(generation: &Generation, pos: ChunkPos) -> Result<Chunk, PersistenceError> {
+    let generator = generation.generator.clone();
+    let cancelled = generation.cancelled.clone();
+
+    let generated = task::spawn_blocking(move || {
+        (!cancelled.load(Ordering::Relaxed)).then(|| generator.generate_chunk(pos))
+    })
+    .await;
+
+    match generated {
+        Ok(Some(chunk)) => Ok(chunk),
+        // The job is aborted when it's cancelled, so nothing is waiting for it
+        Ok(None) => std::future::pending().await,
+        Err(error) => Err(PersistenceError::ChunkPanicked {
+            pos,
+            error: panic_message(error),
+        }),
+    }
+}
+
+fn panic_message(error: JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+
+    let payload: Box<dyn Any> = error.into_panic();
+
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+/// Loads a chunk from the database, or generates it if it hasn't been stored yet.
+/// Also returns whether the chunk needs to be written back to the database.
+async fn load_chunk(
+    ctx: &mut TaskContext,
+    db: &SqlitePool,
+    generator: &Generation,
+    chunk_pos: ChunkPos,
+) -> Result<(Chunk, bool), PersistenceError> {
+    let row = retry("load chunk", || {
+        sqlx::query!(
+            "SELECT data FROM chunks WHERE x = ? AND y = ? AND z = ?",
+            chunk_pos.x,
+            chunk_pos.y,
+            chunk_pos.z
+        )
+        .fetch_optional(db)
+    })
+    .await?;
+
+    if let Some(row) = row {
+        match decode_chunk(&row.data) {
+            Ok(decoded) => return Ok((decoded.value, decoded.upgraded)),
+            Err(error) => {
+                let error = error.to_string();
+
+                retry("quarantine corrupt chunk", || {
+                    quarantine_chunk(db, StorageMode::Full, chunk_pos, &row.data, &error)
+                })
+                .await?;
+
+                report(
+                    ctx,
+                    PersistenceError::CorruptChunk {
+                        pos: chunk_pos,
+                        error,
+                    },
+                )
+                .await;
+            }
+        }
+    }
+
+    let chunk = generate(generator, chunk_pos).await?;
+    let data = encode_chunk(&chunk);
+
+    let inserted = retry("store generated chunk", || {
+        sqlx::query!(
+            "INSERT INTO chunks (x, y, z, data) VALUES (?, ?, ?, ?)",
+            chunk_pos.x,
+            chunk_pos.y,
+            chunk_pos.z,
+            data
+        )
+        .execute(db)
+    })
+    .await;
+
+    // The chunk is still usable if it couldn't be stored, it'll just be saved later on
+    let needs_saving = match inserted {
+        Ok(_) => false,
+        Err(error) => {
+            report(ctx, error).await;
+            true
+        }
+    };
+
+    Ok((chunk, needs_saving))
+}
+
+/// Loads a chunk the player has changed from the database, or generates it if it hasn't been
+/// changed. Chunks are only stored once they've been changed, and generated ones are left out.
+async fn load_chunk_delta(
+    ctx: &mut TaskContext,
+    db: &SqlitePool,
+    generator: &Generation,
+    chunk_pos: ChunkPos,
+) -> Result<(Chunk, bool), PersistenceError> {
+    let row = retry("load chunk", || {
+        sqlx::query!(
+            "SELECT data FROM chunks WHERE x = ? AND y = ? AND z = ?",
+            chunk_pos.x,
+            chunk_pos.y,
+            chunk_pos.z
+        )
+        .fetch_optional(db)
+    })
+    .await?;
+
+    if let Some(row) = row {
+        match decode_chunk(&row.data) {
+            Ok(decoded) => return Ok((decoded.value, decoded.upgraded)),
+            Err(error) => {
+                let error = error.to_string();
+
+                // Changed chunks are stored in full, like every chunk is in full storage
+                retry("quarantine corrupt chunk", || {
+                    quarantine_chunk(db, StorageMode::Full, chunk_pos, &row.data, &error)
+                })
+                .await?;
+
+                report(
+                    ctx,
+                    PersistenceError::CorruptChunk {
+                        pos: chunk_pos,
+                        error,
+                    },
+                )
+                .await;
+            }
+        }
+    }
+
+    Ok((generate(generator, chunk_pos).await?, false))
+}
+
+/// Older versions stored the chunks the player changed in delta worlds as just the blocks that
+/// differ from the generated ones, which only the generator they were made with can rebuild.
+/// Those chunks are stored in full instead, so that only unchanged terrain depends on the
+/// generator. Worlds with changes left from a different generator are refused.
+pub(super) async fn store_changed_chunks_in_full(
+    db: &SqlitePool,
+    metadata: &WorldMetadata,
+) -> Result<(), PersistenceError> {
+    let rows = retry("load chunk changes", || {
+        sqlx::query!("SELECT x, y, z, data FROM chunk_deltas").fetch_all(db)
+    })
+    .await?;
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    if metadata.generator_version != GENERATOR_VERSION {
+        return Err(PersistenceError::OutdatedGenerator {
+            world: metadata.generator_version,
+            current: GENERATOR_VERSION,
+        });
+    }
+
+    let generation = Generation {
+        generator: Arc::new(LevelGenerator::with_preset(
+            metadata.seed,
+            metadata.preset.clone(),
+        )),
+        cancelled: Arc::new(AtomicBool::new(false)),
+    };
+
+    let mut chunks = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let pos = ChunkPos::new(row.x as i32, row.y as i32, row.z as i32);
+
+        let delta = match decode_chunk_delta(&row.data) {
+            Ok(decoded) => decoded.value,
+            Err(error) => {
+                let error = error.to_string();
+
+                retry("quarantine corrupt chunk", || {
+                    quarantine_chunk(db, StorageMode::Delta, pos, &row.data, &error)
+                })
+                .await?;
+
+                error!("{}", PersistenceError::CorruptChunk { pos, error });
+                continue;
+            }
+        };
+
+        // The changes stay where they are, and the chunk will panic again once it's loaded
+        let mut chunk = match generate(&generation, pos).await {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                error!("{error}");
+                continue;
+            }
+        };
+
+        chunk.apply(&delta);
+        chunks.push((pos, encode_chunk(&chunk)));
+    }
+
+    retry("store changed chunks in full", || {
+        write_changed_chunks(db, &chunks)
+    })
+    .await?;
+
+    info!("Stored {} changed chunks in full", chunks.len());
+    Ok(())
+}
+
+async fn write_changed_chunks(
+    db: &SqlitePool,
+    chunks: &[(ChunkPos, Vec<u8>)],
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    for (pos, data) in chunks {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO chunks (x, y, z, data) VALUES (?, ?, ?, ?)",
+            pos.x,
+            pos.y,
+            pos.z,
+            data
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM chunk_deltas WHERE x = ? AND y = ? AND z = ?",
+            pos.x,
+            pos.y,
+            pos.z
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{block::Block, level::Dirty, position::LocalPos};
+
+    use super::*;
+
+    /// Adds a chunk to the level as if it had just loaded, and returns the chunks that were
+    /// marked for meshing because of it, clearing those marks the way meshing does.
+    fn load(world: &mut World, pos: ChunkPos, chunk: Chunk) -> Vec<ChunkPos> {
+        insert_loaded_chunk(world, pos, chunk, false);
+        take_marked(world)
+    }
+
+    /// Like [`load`], but for a chunk that panicked while generating.
+    fn panic(world: &mut World, pos: ChunkPos) -> Vec<ChunkPos> {
+        insert_panicked_chunk(world, pos);
+        take_marked(world)
+    }
+
+    fn take_marked(world: &mut World) -> Vec<ChunkPos> {
+        let marked: Vec<(Entity, ChunkPos)> = world
+            .query_filtered::<(Entity, &ChunkPos), With<Dirty>>()
+            .iter(world)
+            .map(|(entity, &pos)| (entity, pos))
+            .collect();
+
+        for &(entity, pos) in &marked {
+            world.entity_mut(entity).remove::<Dirty>();
+
+            let mut level = world.resource_mut::<Level>();
+            let surrounded = level.is_surrounded(pos);
+            level.chunks.get_mut(&pos).unwrap().meshed = surrounded;
+        }
+
+        marked.into_iter().map(|(_, pos)| pos).collect()
+    }
+
+    #[test]
+    fn chunks_are_meshed_once_their_last_neighbor_loads() {
+        let mut world = World::new();
+        world.init_resource::<Level>();
+
+        let center = ChunkPos::new(0, 0, 0);
+        let mut chunk = Chunk::new();
+        chunk.set(LocalPos::new(8, 8, 8), Block::Rock);
+
+        assert!(load(&mut world, center, chunk).is_empty());
+
+        let mut neighbors = Vec::new();
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    neighbors.push(ChunkPos::new(dx, dy, dz));
+                }
+            }
+        }
+
+        neighbors.retain(|&pos| pos != center);
+        let last = neighbors.pop().unwrap();
+
+        for pos in neighbors {
+            assert!(load(&mut world, pos, Chunk::new()).is_empty());
+        }
+
+        assert_eq!(load(&mut world, last, Chunk::new()), [center]);
+
+        // Chunks further out don't touch it again
+        assert!(load(&mut world, ChunkPos::new(2, 0, 0), Chunk::new()).is_empty());
+    }
+
+    #[test]
+    fn chunks_next_to_a_panicked_chunk_are_still_meshed() {
+        let mut world = World::new();
+        world.init_resource::<Level>();
+
+        let center = ChunkPos::new(0, 0, 0);
+        let mut chunk = Chunk::new();
+        chunk.set(LocalPos::new(8, 8, 8), Block::Rock);
+
+        assert!(load(&mut world, center, chunk).is_empty());
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let pos = ChunkPos::new(dx, dy, dz);
+
+                    if pos != center && pos != ChunkPos::new(1, 1, 1) {
+                        assert!(load(&mut world, pos, Chunk::new()).is_empty());
+                    }
+                }
+            }
+        }
+
+        assert_eq!(panic(&mut world, ChunkPos::new(1, 1, 1)), [center]);
+        assert!(world
+            .resource::<Level>()
+            .chunk(ChunkPos::new(1, 1, 1))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn panics_are_reported_with_their_message() {
+        let error = task::spawn_blocking(|| panic!("chunk {} is broken", 3))
+            .await
+            .unwrap_err();
+
+        assert_eq!(panic_message(error), "chunk 3 is broken");
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+
+use crate::{loader::ChunkMaterial, position::ChunkPos, voxel_mesh::MeshingMode};
+
+use super::{Dirty, Level, Modified};
+
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct ChunkMeshSettings {
+    /// The most finished meshes to add each frame, so that a burst of chunks arriving at once
+    /// is spread over several frames.
+    pub uploads_per_frame: usize,
+    pub mode: MeshingMode,
+}
+
+impl Default for ChunkMeshSettings {
+    fn default() -> Self {
+        Self {
+            uploads_per_frame: 16,
+            mode: MeshingMode::default(),
+        }
+    }
+}
+
+/// Meshes being built in the background. Starting a new mesh for a chunk drops the task for
+/// its old one, so a mesh for a chunk that has changed again since is never added.
+#[derive(Default, Resource)]
+pub(super) struct ChunkMeshTasks {
+    tasks: HashMap<ChunkPos, MeshTask>,
+}
+
+struct MeshTask {
+    entity: Entity,
+    task: Task<Mesh>,
+}
+
+/// Starts meshing every dirty chunk on the async compute pool, from a snapshot of the chunk
+/// and the edges of its neighbors. Chunks with neighbors that aren't loaded are skipped, and
+/// marked dirty again once the last of them loads.
+pub(super) fn start_chunk_meshing(
+    mut commands: Commands,
+    mut level: ResMut<Level>,
+    mut tasks: ResMut<ChunkMeshTasks>,
+    settings: Res<ChunkMeshSettings>,
+    dirty_query: Query<(Entity, &ChunkPos, Has<Modified>), With<Dirty>>,
+) {
+    let pool = AsyncComputeTaskPool::get();
+    let mode = settings.mode;
+
+    for (entity, &chunk_pos, modified) in &dirty_query {
+        commands.entity(entity).remove::<Dirty>();
+
+        // Wait for the neighbors, rather than meshing the chunk's edges against missing blocks
+        let surrounded = level.is_surrounded(chunk_pos);
+
+        let Some(loaded) = level.chunks.get_mut(&chunk_pos) else {
+            continue;
+        };
+
+        loaded.meshed = surrounded;
+
+        if !surrounded {
+            continue;
+        }
+
+        let snapshot = level
+            .needs_mesh(chunk_pos)
+            .then(|| level.snapshot(chunk_pos))
+            .flatten();
+
+        let Some(snapshot) = snapshot else {
+            tasks.tasks.remove(&chunk_pos);
+
+            // The chunk became hidden, so its entity is only worth keeping until it's saved
+            if modified {
+                commands.entity(entity).remove::<Mesh3d>();
+            } else if let Some(loaded) = level.chunks.get_mut(&chunk_pos) {
+                loaded.entity = None;
+                commands.entity(entity).despawn_recursive();
+            }
+
+            continue;
+        };
+
+        let task = pool.spawn(async move { snapshot.render(mode).build() });
+        tasks.tasks.insert(chunk_pos, MeshTask { entity, task });
+    }
+}
+
+/// Adds finished meshes to their chunks, up to [`ChunkMeshSettings::uploads_per_frame`].
+pub(super) fn apply_chunk_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut tasks: ResMut<ChunkMeshTasks>,
+    level: Res<Level>,
+    material: Res<ChunkMaterial>,
+    settings: Res<ChunkMeshSettings>,
+) {
+    let mut uploaded = 0;
+
+    tasks.tasks.retain(|&chunk_pos, mesh_task| {
+        if uploaded >= settings.uploads_per_frame {
+            return true;
+        }
+
+        let Some(mesh) = block_on(poll_once(&mut mesh_task.task)) else {
+            return true;
+        };
+
+        // Drop meshes for chunks that were unloaded or lost their entity in the meantime
+        if level.entity(chunk_pos) == Some(mesh_task.entity) {
+            commands
+                .entity(mesh_task.entity)
+                .insert((Mesh3d(meshes.add(mesh)), MeshMaterial3d(material.0.clone())));
+
+            uploaded += 1;
+        }
+
+        false
+    });
+}
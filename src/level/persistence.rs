@@ -0,0 +1,215 @@
+use std::{fmt, future::Future, time::Duration};
+
+use bevy::prelude::*;
+use bevy_tokio_tasks::TaskContext;
+use sqlx::SqlitePool;
+use tokio::time::sleep;
+
+use crate::position::ChunkPos;
+
+use super::world::{unix_time, StorageMode};
+
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Sent whenever reading from or writing to the level database fails, after any retries.
+/// The game keeps running, but the player is warned since their world may not be saved.
+#[derive(Debug, Clone, Event)]
+pub enum PersistenceError {
+    Database {
+        action: String,
+        error: String,
+    },
+    CorruptChunk {
+        pos: ChunkPos,
+        error: String,
+    },
+    CorruptInventory {
+        error: String,
+    },
+    /// Another world's name maps to the same directory, and it was there first.
+    NameTaken {
+        requested: String,
+        stored: String,
+    },
+    /// The preset stored with the world can't be read, so its terrain can't be generated.
+    CorruptPreset {
+        error: String,
+    },
+    /// Loading or generating a chunk panicked. It's left out until it goes out of range and
+    /// comes back, rather than crashing the game or being tried again straight away.
+    ChunkPanicked {
+        pos: ChunkPos,
+        error: String,
+    },
+    /// The world has changes stored by an older version as the blocks that differ from the
+    /// generated terrain, which can only be rebuilt by the generator they were made with.
+    OutdatedGenerator {
+        world: u32,
+        current: u32,
+    },
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database { action, error } => {
+                write!(f, "Failed to {action}: {error}")
+            }
+            Self::CorruptChunk { pos, error } => write!(
+                f,
+                "Chunk at {}, {}, {} was corrupt and has been regenerated ({error})",
+                pos.x, pos.y, pos.z
+            ),
+            Self::CorruptInventory { error } => {
+                write!(f, "Inventory was corrupt and has been reset ({error})")
+            }
+            Self::NameTaken { requested, stored } => write!(
+                f,
+                "The world \"{requested}\" would be stored in the same folder as \"{stored}\", so \
+                it needs a different name"
+            ),
+            Self::CorruptPreset { error } => {
+                write!(
+                    f,
+                    "World preset is corrupt, so the world can't be opened ({error})"
+                )
+            }
+            Self::ChunkPanicked { pos, error } => write!(
+                f,
+                "Chunk at {}, {}, {} couldn't be loaded and has been left out ({error})",
+                pos.x, pos.y, pos.z
+            ),
+            Self::OutdatedGenerator { world, current } => write!(
+                f,
+                "World has changes that were stored against generator version {world} terrain, \
+                but this version of the game generates version {current} terrain, so they can't \
+                be applied to it"
+            ),
+        }
+    }
+}
+
+/// Runs a database operation, retrying with exponential backoff if it fails.
+/// Most failures are transient (the database is locked by another process, or the disk is
+/// briefly unavailable), so it's worth waiting a little before giving up.
+pub async fn retry<T, E, F, Fut>(action: &str, mut operation: F) -> Result<T, PersistenceError>
+where
+    E: fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < MAX_ATTEMPTS => {
+                warn!("Failed to {action} (attempt {attempt}/{MAX_ATTEMPTS}): {error}");
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            Err(error) => {
+                return Err(PersistenceError::Database {
+                    action: action.to_string(),
+                    error: error.to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// Logs the error and sends it to the main thread as an event.
+pub async fn report(ctx: &mut TaskContext, error: PersistenceError) {
+    error!("{error}");
+
+    ctx.run_on_main_thread(move |ctx| {
+        ctx.world.send_event(error);
+    })
+    .await;
+}
+
+/// Moves a chunk row that couldn't be decoded out of the way, so that it can be regenerated
+/// without losing the original data.
+pub async fn quarantine_chunk(
+    db: &SqlitePool,
+    storage: StorageMode,
+    pos: ChunkPos,
+    data: &[u8],
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let now = unix_time();
+    let mut tx = db.begin().await?;
+
+    let source = match storage {
+        StorageMode::Full => "chunk",
+        StorageMode::Delta => "chunk_delta",
+    };
+
+    sqlx::query!(
+        "
+        INSERT INTO quarantine (source, x, y, z, data, error, quarantined_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ",
+        source,
+        pos.x,
+        pos.y,
+        pos.z,
+        data,
+        error,
+        now
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    match storage {
+        StorageMode::Full => {
+            sqlx::query!(
+                "DELETE FROM chunks WHERE x = ? AND y = ? AND z = ?",
+                pos.x,
+                pos.y,
+                pos.z
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        StorageMode::Delta => {
+            sqlx::query!(
+                "DELETE FROM chunk_deltas WHERE x = ? AND y = ? AND z = ?",
+                pos.x,
+                pos.y,
+                pos.z
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await
+}
+
+/// Keeps a copy of an inventory that couldn't be decoded, before it's replaced.
+pub async fn quarantine_inventory(
+    db: &SqlitePool,
+    data: &[u8],
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let now = unix_time();
+
+    sqlx::query!(
+        "
+        INSERT INTO quarantine (source, data, error, quarantined_at)
+        VALUES ('inventory', ?, ?, ?)
+        ",
+        data,
+        error,
+        now
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
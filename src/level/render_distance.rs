@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+
+use crate::position::{ChunkPos, CHUNK_SIZE};
+
+/// How much loading prefers chunks in front of the camera. At 0.5, a chunk straight ahead
+/// is loaded as if it were half as far away, and one straight behind as if 1.5 times as far.
+const VIEW_DIRECTION_WEIGHT: f32 = 0.5;
+
+/// How much further than the render distance loaded chunks can be. The chunks around the
+/// drawn ones are loaded too, and diagonally those are up to √3 chunks further out.
+const LOADED_MARGIN: i32 = 2;
+
+/// Controls which chunks are loaded around the player. Both loading and unloading measure
+/// the distance between chunk positions, so a chunk is never unloaded as soon as it's loaded.
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct RenderDistance {
+    /// Chunks within this many chunks of the player's chunk are drawn. Chunks are only meshed
+    /// once every chunk around them has loaded, so the chunks just outside this are loaded too,
+    /// without being drawn.
+    pub chunks: i32,
+    /// How many chunks further than that chunks are kept before they're unloaded, so that
+    /// moving back and forth across the edge doesn't keep loading and unloading them.
+    pub hysteresis: i32,
+}
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        Self {
+            chunks: 8,
+            hysteresis: 2,
+        }
+    }
+}
+
+impl RenderDistance {
+    pub const MIN: i32 = 2;
+    pub const MAX: i32 = 32;
+
+    pub fn set_chunks(&mut self, chunks: i32) {
+        self.chunks = chunks.clamp(Self::MIN, Self::MAX);
+    }
+
+    /// Where fog starts and where it hides everything, in blocks from the camera. It ends two
+    /// chunks short of the render distance, since the camera can be anywhere in its chunk, and
+    /// the nearest corner of the closest chunk that isn't drawn can be up to √3 chunks closer
+    /// than the chunk's position.
+    pub fn fog_range(&self) -> Vec2 {
+        let chunk_size = CHUNK_SIZE as f32;
+        let end = (self.chunks - 2).max(1) as f32 * chunk_size;
+        Vec2::new(end - chunk_size, end)
+    }
+
+    /// Whether a chunk is within the render distance, or is next to one that is, diagonals
+    /// included.
+    pub fn should_load(&self, player_chunk: ChunkPos, pos: ChunkPos) -> bool {
+        // The distance to whichever of the chunk and its neighbors is closest to the player
+        let closest = |offset: i32| (offset.abs() - 1).max(0);
+        let dx = closest(pos.x - player_chunk.x);
+        let dy = closest(pos.y - player_chunk.y);
+        let dz = closest(pos.z - player_chunk.z);
+
+        dx * dx + dy * dy + dz * dz <= self.chunks * self.chunks
+    }
+
+    pub fn should_unload(&self, player_chunk: ChunkPos, pos: ChunkPos) -> bool {
+        let unload_distance = self.chunks + LOADED_MARGIN + self.hysteresis.max(0);
+        chunk_distance_sq(player_chunk, pos) > unload_distance * unload_distance
+    }
+
+    /// Orders chunks for loading, lowest first. Closer chunks come first, with chunks in
+    /// the direction the camera is facing counted as closer than those behind it.
+    pub fn load_priority(&self, player_pos: Vec3, view_direction: Vec3, pos: ChunkPos) -> f32 {
+        let center = (pos.world_pos() + Vec3::splat(CHUNK_SIZE as f32 / 2.0)) / CHUNK_SIZE as f32;
+        let offset = center - player_pos / CHUNK_SIZE as f32;
+        let alignment = offset.normalize_or_zero().dot(view_direction);
+
+        offset.length() * (1.0 - VIEW_DIRECTION_WEIGHT * alignment)
+    }
+}
+
+/// Squared distance between two chunks, in chunks.
+pub fn chunk_distance_sq(a: ChunkPos, b: ChunkPos) -> i32 {
+    let dx = a.x - b.x;
+    let dy = a.y - b.y;
+    let dz = a.z - b.z;
+    dx * dx + dy * dy + dz * dz
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_within_the_render_distance_have_every_neighbor_loaded() {
+        let render_distance = RenderDistance {
+            chunks: 5,
+            hysteresis: 0,
+        };
+
+        let player_chunk = ChunkPos::new(0, 0, 0);
+        let range = render_distance.chunks + LOADED_MARGIN + 1;
+
+        for x in -range..=range {
+            for y in -range..=range {
+                for z in -range..=range {
+                    let pos = ChunkPos::new(x, y, z);
+                    let drawn = chunk_distance_sq(player_chunk, pos)
+                        <= render_distance.chunks * render_distance.chunks;
+
+                    if drawn {
+                        for dx in -1..=1 {
+                            for dy in -1..=1 {
+                                for dz in -1..=1 {
+                                    let neighbor = pos + ChunkPos::new(dx, dy, dz);
+                                    assert!(render_distance.should_load(player_chunk, neighbor));
+                                }
+                            }
+                        }
+                    }
+
+                    if render_distance.should_load(player_chunk, pos) {
+                        assert!(!render_distance.should_unload(player_chunk, pos));
+                    }
+                }
+            }
+        }
+    }
+}
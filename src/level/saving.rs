@@ -0,0 +1,431 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{app::AppExit, prelude::*, window::WindowCloseRequested};
+use bevy_tokio_tasks::{TaskContext, TokioTasksRuntime};
+use sqlx::SqlitePool;
+use tokio::{sync::Mutex, time::sleep};
+
+use crate::{
+    chunk::Chunk,
+    inventory::Inventory,
+    physics::PhysicalPosition,
+    player::{Player, PlayerCamera},
+    position::ChunkPos,
+};
+
+use super::{
+    backup::Restoring,
+    cache::ChunkCache,
+    format::{decode_inventory, encode_chunk, encode_inventory},
+    generator::LevelGenerator,
+    persistence::{quarantine_inventory, report, retry, PersistenceError},
+    with_level,
+    world::{StorageMode, WorldMetadata},
+    Level, LevelDatabase, Modified,
+};
+
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct AutosaveSettings {
+    pub interval: Duration,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Held while saving, so that nothing else writes to the database in the middle of a save.
+/// Autosaves take it on the main thread along with their batch, and let go of it before they
+/// need the main thread again, so that the flush on exit can wait for it there.
+#[derive(Debug, Default, Clone, Resource, Deref)]
+pub(super) struct SaveLock(Arc<Mutex<()>>);
+
+/// Inserted once the player has been loaded from the database, so that it isn't
+/// overwritten by the default position and inventory before then.
+#[derive(Debug, Clone, Copy, Resource)]
+struct PlayerLoaded;
+
+#[derive(Debug)]
+struct SaveBatch {
+    chunks: Vec<(ChunkPos, Chunk)>,
+    unloaded: Vec<(ChunkPos, u64)>,
+    player: Option<PlayerSave>,
+    storage: StorageMode,
+    generator: LevelGenerator,
+}
+
+/// What needs to be written to the database for a chunk.
+#[derive(Debug)]
+enum ChunkWrite {
+    Chunk(Vec<u8>),
+    /// The chunk is the same as when it was generated, so it doesn't need to be stored.
+    Unchanged,
+}
+
+#[derive(Debug)]
+struct PlayerSave {
+    translation: Vec3,
+    rotation: (f32, f32, f32),
+    inventory: Inventory,
+}
+
+pub(super) fn start_saving(
+    db: Res<LevelDatabase>,
+    save_lock: Res<SaveLock>,
+    runtime: Res<TokioTasksRuntime>,
+) {
+    let db = db.0.clone();
+    let save_lock = save_lock.clone();
+
+    runtime.spawn_background_task(move |mut ctx| async move {
+        {
+            let _guard = save_lock.lock().await;
+            load_player(&mut ctx, &db).await;
+        }
+
+        loop {
+            let interval = ctx
+                .run_on_main_thread(|ctx| ctx.world.resource::<AutosaveSettings>().interval)
+                .await;
+
+            sleep(interval).await;
+
+            let save_lock = save_lock.clone();
+
+            let collected = ctx
+                .run_on_main_thread(move |ctx| {
+                    let guard = save_lock.0.try_lock_owned().ok()?;
+                    Some((guard, collect_save_batch(ctx.world)))
+                })
+                .await;
+
+            // A backup is being restored, so this save is skipped
+            let Some((guard, batch)) = collected else {
+                continue;
+            };
+
+            let writes = encode_chunks(&batch);
+            let result = retry("save level", || write_save_batch(&db, &batch, &writes)).await;
+            let saved = result.is_ok();
+
+            drop(guard);
+
+            if let Err(error) = result {
+                report(&mut ctx, error).await;
+            }
+
+            ctx.run_on_main_thread(move |ctx| finish_save_batch(ctx.world, batch, saved))
+                .await;
+        }
+    });
+}
+
+/// Loads the player's position and inventory from the database and applies them.
+pub(super) async fn load_player(ctx: &mut TaskContext, db: &SqlitePool) {
+    // Without the player, there's nothing sensible to fall back to, so keep trying
+    let player = loop {
+        let player = retry("load player", || {
+            sqlx::query!("SELECT x, y, z, roll, pitch, yaw, inventory FROM player").fetch_one(db)
+        })
+        .await;
+
+        match player {
+            Ok(player) => break player,
+            Err(error) => {
+                report(ctx, error).await;
+                sleep(Duration::from_secs(5)).await;
+            }
+        }
+    };
+
+    let player_pos = Vec3::new(player.x as f32, player.y as f32, player.z as f32);
+    let player_rotation = Quat::from_euler(
+        EulerRot::XYZ,
+        player.roll as f32,
+        player.pitch as f32,
+        player.yaw as f32,
+    );
+
+    let inventory = match player.inventory {
+        Some(data) => match decode_inventory(&data) {
+            Ok(decoded) => decoded.value,
+            Err(error) => {
+                let error = error.to_string();
+
+                if let Err(error) = retry("quarantine corrupt inventory", || {
+                    quarantine_inventory(db, &data, &error)
+                })
+                .await
+                {
+                    report(ctx, error).await;
+                }
+
+                report(ctx, PersistenceError::CorruptInventory { error }).await;
+                Inventory::default()
+            }
+        },
+        None => Inventory::default(),
+    };
+
+    ctx.run_on_main_thread(move |ctx| {
+        let (mut position, mut transform) = ctx
+            .world
+            .query_filtered::<(&mut PhysicalPosition, &mut Transform), With<Player>>()
+            .single_mut(ctx.world);
+
+        position.teleport(player_pos);
+        transform.translation = player_pos;
+
+        ctx.world
+            .query_filtered::<&mut Transform, With<PlayerCamera>>()
+            .single_mut(ctx.world)
+            .rotation = player_rotation;
+
+        ctx.world.insert_resource(inventory);
+        ctx.world.insert_resource(PlayerLoaded);
+    })
+    .await;
+}
+
+/// Writes everything that hasn't been saved yet before the game closes, blocking until it's done.
+pub(super) fn flush_on_exit(world: &mut World) {
+    let exiting = !world.resource::<Events<AppExit>>().is_empty()
+        || !world.resource::<Events<WindowCloseRequested>>().is_empty();
+
+    if !exiting {
+        return;
+    }
+
+    let Some(db) = world.get_resource::<LevelDatabase>().map(|db| db.0.clone()) else {
+        return;
+    };
+
+    // Anything unsaved from before a restore would be written over the backup
+    if world.contains_resource::<Restoring>() {
+        warn!("Not saving the level before exiting, since a backup is being restored");
+        return;
+    }
+
+    let runtime = world
+        .resource::<TokioTasksRuntime>()
+        .runtime()
+        .handle()
+        .clone();
+
+    // An autosave that's still writing has already taken its chunks' `Modified` markers, so it
+    // has to commit before they're missed here. Until the player has loaded, the lock is only
+    // held by loading them, which needs this thread and has nothing to save.
+    let _guard = world
+        .contains_resource::<PlayerLoaded>()
+        .then(|| runtime.block_on(world.resource::<SaveLock>().clone().0.lock_owned()));
+
+    // That autosave can't hand its chunks back if it failed, so they're all saved again
+    let in_flight: Vec<ChunkPos> = world.resource::<ChunkCache>().in_flight().collect();
+
+    with_level(world, |level, commands| {
+        for pos in in_flight {
+            if let Some(entity) = level.spawn_entity(commands, pos) {
+                commands.entity(entity).insert(Modified);
+            }
+        }
+    });
+
+    let batch = collect_save_batch(world);
+    let writes = encode_chunks(&batch);
+
+    let result = runtime.block_on(retry("save level before exiting", || {
+        write_save_batch(&db, &batch, &writes)
+    }));
+
+    let saved = match result {
+        Ok(()) => {
+            info!("Saved level before exiting");
+            true
+        }
+        Err(error) => {
+            error!("{error}");
+            false
+        }
+    };
+
+    finish_save_batch(world, batch, saved);
+}
+
+/// Gathers everything that needs to be saved. Modified chunks are unmarked, and will
+/// be marked again if saving them fails.
+fn collect_save_batch(world: &mut World) -> SaveBatch {
+    let modified: Vec<(Entity, ChunkPos)> = world
+        .query_filtered::<(Entity, &ChunkPos), With<Modified>>()
+        .iter(world)
+        .map(|(entity, chunk_pos)| (entity, *chunk_pos))
+        .collect();
+
+    for (entity, _pos) in &modified {
+        world.entity_mut(*entity).remove::<Modified>();
+    }
+
+    let level = world.resource::<Level>();
+
+    let mut chunks: Vec<(ChunkPos, Chunk)> = modified
+        .iter()
+        .filter_map(|(_, pos)| Some((*pos, level.chunk(*pos)?.clone())))
+        .collect();
+
+    let mut cache = world.resource_mut::<ChunkCache>();
+    cache.start_saving(chunks.iter().map(|&(pos, _)| pos));
+
+    let unloaded = cache
+        .dirty()
+        .map(|(pos, version, _)| (pos, version))
+        .collect();
+
+    chunks.extend(cache.dirty().map(|(pos, _, chunk)| (pos, chunk.clone())));
+
+    for (_, chunk) in &mut chunks {
+        chunk.optimize();
+    }
+
+    let player = if world.contains_resource::<PlayerLoaded>() {
+        let translation = world
+            .query_filtered::<&PhysicalPosition, With<Player>>()
+            .single(world)
+            .current;
+
+        let rotation = world
+            .query_filtered::<&Transform, With<PlayerCamera>>()
+            .single(world)
+            .rotation
+            .to_euler(EulerRot::XYZ);
+
+        Some(PlayerSave {
+            translation,
+            rotation,
+            inventory: world.resource::<Inventory>().clone(),
+        })
+    } else {
+        None
+    };
+
+    SaveBatch {
+        chunks,
+        unloaded,
+        player,
+        storage: world.resource::<WorldMetadata>().storage,
+        generator: world.resource::<LevelGenerator>().clone(),
+    }
+}
+
+/// Encodes the chunks in the batch. In delta storage, this regenerates each chunk to find
+/// whether it has changed, so it's done once up front rather than on every attempt to save.
+/// Changed chunks are stored in full, so that they stay as they are if the generator changes.
+fn encode_chunks(batch: &SaveBatch) -> Vec<(ChunkPos, ChunkWrite)> {
+    batch
+        .chunks
+        .iter()
+        .map(|(pos, chunk)| {
+            let write = match batch.storage {
+                StorageMode::Full => ChunkWrite::Chunk(encode_chunk(chunk)),
+                StorageMode::Delta => {
+                    if chunk.diff(&batch.generator.generate_chunk(*pos)).is_empty() {
+                        ChunkWrite::Unchanged
+                    } else {
+                        ChunkWrite::Chunk(encode_chunk(chunk))
+                    }
+                }
+            };
+
+            (*pos, write)
+        })
+        .collect()
+}
+
+/// Writes the whole batch in a single transaction, so that a save is never half applied.
+async fn write_save_batch(
+    db: &SqlitePool,
+    batch: &SaveBatch,
+    writes: &[(ChunkPos, ChunkWrite)],
+) -> Result<(), sqlx::Error> {
+    if writes.is_empty() && batch.player.is_none() {
+        return Ok(());
+    }
+
+    let mut tx = db.begin().await?;
+
+    for (chunk_pos, write) in writes {
+        match write {
+            ChunkWrite::Chunk(data) => {
+                sqlx::query!(
+                    "
+                    INSERT INTO chunks (x, y, z, data) VALUES (?, ?, ?, ?)
+                    ON CONFLICT (x, y, z) DO UPDATE SET data = excluded.data
+                    ",
+                    chunk_pos.x,
+                    chunk_pos.y,
+                    chunk_pos.z,
+                    data
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            ChunkWrite::Unchanged => {
+                sqlx::query!(
+                    "DELETE FROM chunks WHERE x = ? AND y = ? AND z = ?",
+                    chunk_pos.x,
+                    chunk_pos.y,
+                    chunk_pos.z
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+
+    if let Some(player) = &batch.player {
+        let inventory = encode_inventory(&player.inventory);
+
+        sqlx::query!(
+            "
+            UPDATE player SET
+                x = ?, y = ?, z = ?,
+                roll = ?, pitch = ?, yaw = ?,
+                inventory = ?
+            ",
+            player.translation.x,
+            player.translation.y,
+            player.translation.z,
+            player.rotation.0,
+            player.rotation.1,
+            player.rotation.2,
+            inventory
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+fn finish_save_batch(world: &mut World, batch: SaveBatch, saved: bool) {
+    let mut cache = world.resource_mut::<ChunkCache>();
+    cache.finish_saving();
+
+    if saved {
+        for (pos, version) in batch.unloaded {
+            cache.mark_saved(pos, version);
+        }
+
+        return;
+    }
+
+    // Chunks that became hidden since may have lost their entity, so they need a new one
+    with_level(world, |level, commands| {
+        for &(pos, _) in &batch.chunks {
+            if let Some(entity) = level.spawn_entity(commands, pos) {
+                commands.entity(entity).insert(Modified);
+            }
+        }
+    });
+}
@@ -0,0 +1,268 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+
+use super::generator::preset::WorldPreset;
+
+pub const WORLDS_DIR: &str = "worlds";
+pub const DEFAULT_WORLD_NAME: &str = "World";
+pub const LEVEL_FILE: &str = "level.sqlite";
+
+/// The files SQLite keeps next to a database in write-ahead log mode.
+const DATABASE_SIDE_FILES: [&str; 2] = ["-wal", "-shm"];
+
+/// Seed used by worlds that were created before the seed was stored in the database.
+pub const LEGACY_SEED: u32 = 42;
+
+/// Generator version stored for worlds that were created before the version was recorded,
+/// whose chunks may have come from any of the generators before then.
+pub const LEGACY_GENERATOR_VERSION: u32 = 0;
+
+/// Which world to open, chosen with the `--world <name>`, `--seed <seed>`,
+/// `--storage <full|delta>` and `--preset <name|file>` arguments. The seed, storage mode and
+/// preset are only used if the world doesn't exist yet, otherwise a random seed, full
+/// storage and the default preset are picked.
+#[derive(Debug, Clone, Resource)]
+pub struct WorldSelection {
+    pub name: String,
+    pub seed: Option<u32>,
+    pub storage: Option<StorageMode>,
+    pub preset: Option<WorldPreset>,
+}
+
+impl Default for WorldSelection {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_WORLD_NAME.to_string(),
+            seed: None,
+            storage: None,
+            preset: None,
+        }
+    }
+}
+
+/// How chunks are stored in the level database.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageMode {
+    /// Every chunk that has been generated is stored in full.
+    #[default]
+    Full,
+    /// Only chunks the player has changed are stored, and the rest are generated again
+    /// whenever they're loaded.
+    Delta,
+}
+
+impl StorageMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Delta => "delta",
+        }
+    }
+}
+
+impl FromStr for StorageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "delta" => Ok(Self::Delta),
+            _ => Err(format!("unknown storage mode `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for StorageMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl WorldSelection {
+    pub fn from_args() -> Self {
+        let mut selection = Self::default();
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--world" => {
+                    if let Some(name) = args.next() {
+                        selection.name = name;
+                    }
+                }
+                "--seed" => match args.next().map(|seed| seed.parse()) {
+                    Some(Ok(seed)) => selection.seed = Some(seed),
+                    _ => warn!("Expected a number after `--seed`"),
+                },
+                "--storage" => match args.next().map(|storage| storage.parse()) {
+                    Some(Ok(storage)) => selection.storage = Some(storage),
+                    _ => warn!("Expected `full` or `delta` after `--storage`"),
+                },
+                "--preset" => match args.next().map(|preset| WorldPreset::load(&preset)) {
+                    Some(Ok(preset)) => selection.preset = Some(preset),
+                    // Carrying on would create the world with the wrong preset for good
+                    Some(Err(error)) => {
+                        error!("Invalid `--preset`: {error}");
+                        process::exit(1);
+                    }
+                    None => warn!("Expected a preset name or file after `--preset`"),
+                },
+                _ => warn!("Ignoring unknown argument `{arg}`"),
+            }
+        }
+
+        selection
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        world_dir(&self.name)
+    }
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct WorldMetadata {
+    pub seed: u32,
+    pub name: String,
+    pub created_at: i64,
+    pub last_played_at: i64,
+    pub generator_version: u32,
+    pub storage: StorageMode,
+    pub preset: WorldPreset,
+}
+
+/// The directory a world is stored in, named after a filesystem-safe version of its display name.
+/// Names that only differ in case or punctuation share a directory, so opening a world checks
+/// the name stored in it as well.
+pub fn world_dir(name: &str) -> PathBuf {
+    let slug: String = name
+        .trim()
+        .chars()
+        .flat_map(|c| {
+            let c = if c.is_alphanumeric() { c } else { '-' };
+            c.to_lowercase()
+        })
+        .collect();
+
+    let slug = slug.trim_matches('-');
+
+    PathBuf::from(WORLDS_DIR).join(if slug.is_empty() { "world" } else { slug })
+}
+
+/// Moves the level that versions of the game from before worlds existed kept in the working
+/// directory into the default world, unless that world already exists. Everything is copied
+/// before the originals are removed, and the database itself is only given its final name once
+/// it's complete, so an interrupted import is started over rather than leaving a broken world.
+/// Returns where the level was moved to, if there was one.
+pub fn import_legacy_level(base: &Path) -> io::Result<Option<PathBuf>> {
+    let legacy = base.join(LEVEL_FILE);
+    let dir = base.join(world_dir(DEFAULT_WORLD_NAME));
+    let imported = dir.join(LEVEL_FILE);
+
+    if !legacy.is_file() || imported.exists() {
+        return Ok(None);
+    }
+
+    fs::create_dir_all(&dir)?;
+
+    // The write-ahead log may hold changes that aren't in the database file yet
+    for suffix in DATABASE_SIDE_FILES {
+        let side_file = with_suffix(&legacy, suffix);
+
+        if side_file.is_file() {
+            fs::copy(&side_file, with_suffix(&imported, suffix))?;
+        }
+    }
+
+    let partial = with_suffix(&imported, ".partial");
+    fs::copy(&legacy, &partial)?;
+    fs::rename(&partial, &imported)?;
+
+    fs::remove_file(&legacy)?;
+
+    for suffix in DATABASE_SIDE_FILES {
+        let side_file = with_suffix(&legacy, suffix);
+
+        if side_file.is_file() {
+            fs::remove_file(side_file)?;
+        }
+    }
+
+    Ok(Some(imported))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory to stand in for the working directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("defaria-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn world_dirs_keep_letters_from_any_language() {
+        assert_eq!(
+            world_dir("My World!"),
+            Path::new(WORLDS_DIR).join("my-world")
+        );
+        assert_eq!(world_dir("世界"), Path::new(WORLDS_DIR).join("世界"));
+        assert_eq!(world_dir("Ünïcode"), Path::new(WORLDS_DIR).join("ünïcode"));
+        assert_eq!(world_dir(" -- "), Path::new(WORLDS_DIR).join("world"));
+    }
+
+    #[test]
+    fn legacy_level_is_imported_into_the_default_world() {
+        let base = temp_dir("import");
+        fs::write(base.join(LEVEL_FILE), "database").unwrap();
+        fs::write(base.join("level.sqlite-wal"), "log").unwrap();
+
+        let imported = import_legacy_level(&base).unwrap();
+        let dir = base.join(world_dir(DEFAULT_WORLD_NAME));
+
+        assert_eq!(imported, Some(dir.join(LEVEL_FILE)));
+        assert_eq!(
+            fs::read_to_string(dir.join(LEVEL_FILE)).unwrap(),
+            "database"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("level.sqlite-wal")).unwrap(),
+            "log"
+        );
+        assert!(!dir.join("level.sqlite-shm").exists());
+        assert!(!base.join(LEVEL_FILE).exists());
+        assert!(!base.join("level.sqlite-wal").exists());
+
+        // Once the default world exists, a level left in the working directory is left alone
+        fs::write(base.join(LEVEL_FILE), "newer").unwrap();
+        assert_eq!(import_legacy_level(&base).unwrap(), None);
+        assert_eq!(
+            fs::read_to_string(dir.join(LEVEL_FILE)).unwrap(),
+            "database"
+        );
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
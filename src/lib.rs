@@ -0,0 +1,16 @@
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::type_complexity)]
+
+pub mod aabb;
+pub mod block;
+pub mod chunk;
+pub mod game_state;
+pub mod inventory;
+pub mod item;
+pub mod level;
+pub mod loader;
+pub mod physics;
+pub mod player;
+pub mod position;
+pub mod ui;
+pub mod voxel_mesh;
@@ -6,7 +6,7 @@ use bevy::{
     render::{
         mesh::MeshVertexBufferLayoutRef,
         render_resource::{
-            AsBindGroup, Extent3d, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            AsBindGroup, Extent3d, RenderPipelineDescriptor, ShaderRef,
             SpecializedMeshPipelineError, TextureDimension, TextureFormat,
         },
     },
@@ -14,10 +14,8 @@ use bevy::{
 use bevy_asset_loader::prelude::*;
 
 use crate::{
-    game_state::GameState,
-    position::LocalPos,
-    ui::ItemImageCache,
-    voxel_mesh::{VoxelFace, VoxelMesh},
+    game_state::GameState, level::RenderDistance, position::BlockPos, ui::ItemImageCache,
+    voxel_mesh::VoxelMesh,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -34,7 +32,13 @@ impl Plugin for LoaderPlugin {
                     .load_collection::<DestroyImages>()
                     .load_collection::<ItemImages>(),
             )
-            .add_systems(OnEnter(GameState::Setup), setup_global_texture_array);
+            .add_systems(OnEnter(GameState::Setup), setup_chunk_material)
+            .add_systems(
+                Update,
+                update_fog_range.run_if(
+                    resource_exists::<ChunkMaterial>.and(resource_changed::<RenderDistance>),
+                ),
+            );
     }
 }
 
@@ -207,38 +211,9 @@ impl DestroyImages {
     }
 }
 
+/// The material every chunk is drawn with.
 #[derive(Debug, Clone, Resource)]
-pub struct GlobalTextureArray {
-    pub textures: Handle<Image>,
-    pub destroy: Handle<Image>,
-}
-
-#[derive(Debug, Default, Clone, Copy, ShaderType)]
-pub struct BlockInteraction {
-    x: u32,
-    y: u32,
-    z: u32,
-    face: u32,
-    value: u32,
-}
-
-impl BlockInteraction {
-    pub fn set(&mut self, pos: LocalPos, face: VoxelFace, value: u32) {
-        self.x = pos.x as u32;
-        self.y = pos.y as u32;
-        self.z = pos.z as u32;
-        self.face = face as u32;
-        self.value = value;
-    }
-
-    pub fn unset(&mut self) {
-        self.value = 0;
-    }
-
-    pub fn is_set(&self) -> bool {
-        self.value > 0
-    }
-}
+pub struct ChunkMaterial(pub Handle<VoxelMaterial>);
 
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
 pub struct VoxelMaterial {
@@ -248,8 +223,22 @@ pub struct VoxelMaterial {
     #[texture(2, dimension = "2d_array")]
     #[sampler(3)]
     pub destroy_texture: Handle<Image>,
+    /// The position of the focused block in `xyz`, and how it's highlighted in `w`: 0 for not
+    /// at all, 1 for focused, and 2 and up for each stage of breaking it.
     #[uniform(4)]
-    pub block_interaction: BlockInteraction,
+    pub highlight: IVec4,
+    /// Where fog starts and where it hides everything, in blocks from the camera.
+    #[uniform(5)]
+    pub fog_range: Vec2,
+}
+
+impl VoxelMaterial {
+    pub fn highlight(pos: Option<BlockPos>, value: u32) -> IVec4 {
+        match pos {
+            Some(pos) => IVec4::new(pos.x, pos.y, pos.z, value as i32),
+            None => IVec4::ZERO,
+        }
+    }
 }
 
 impl Material for VoxelMaterial {
@@ -293,16 +282,33 @@ impl Material for VoxelMaterial {
     }
 }
 
-fn setup_global_texture_array(
+fn setup_chunk_material(
     mut commands: Commands,
     block_images: Res<BlockImages>,
     destroy_images: Res<DestroyImages>,
     mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<VoxelMaterial>>,
+    render_distance: Res<RenderDistance>,
 ) {
-    commands.insert_resource(GlobalTextureArray {
-        textures: create_texture_array(block_images.handles(), &mut images).unwrap(),
-        destroy: create_texture_array(destroy_images.handles(), &mut images).unwrap(),
+    let material = materials.add(VoxelMaterial {
+        array_texture: create_texture_array(block_images.handles(), &mut images).unwrap(),
+        destroy_texture: create_texture_array(destroy_images.handles(), &mut images).unwrap(),
+        highlight: IVec4::ZERO,
+        fog_range: render_distance.fog_range(),
     });
+
+    commands.insert_resource(ChunkMaterial(material));
+}
+
+/// Moves the fog along with the render distance, so that it always hides where chunks end.
+fn update_fog_range(
+    mut materials: ResMut<Assets<VoxelMaterial>>,
+    chunk_material: Res<ChunkMaterial>,
+    render_distance: Res<RenderDistance>,
+) {
+    if let Some(material) = materials.get_mut(&chunk_material.0) {
+        material.fog_range = render_distance.fog_range();
+    }
 }
 
 fn create_texture_array(
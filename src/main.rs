@@ -1,28 +1,13 @@
-#![allow(clippy::too_many_arguments)]
-#![allow(clippy::type_complexity)]
-
-mod aabb;
-mod block;
-mod chunk;
-mod game_state;
-mod inventory;
-mod item;
-mod level;
-mod loader;
-mod physics;
-mod player;
-mod position;
-mod ui;
-mod voxel_mesh;
-
 use bevy::prelude::*;
 use bevy_tokio_tasks::TokioTasksPlugin;
-use game_state::{GameState, Paused};
-use level::LevelPlugin;
-use loader::LoaderPlugin;
-use physics::PhysicsPlugin;
-use player::PlayerPlugin;
-use ui::UiPlugin;
+use defaria::{
+    game_state::{GameState, Paused},
+    level::{LevelPlugin, WorldSelection},
+    loader::LoaderPlugin,
+    physics::PhysicsPlugin,
+    player::PlayerPlugin,
+    ui::UiPlugin,
+};
 
 fn main() {
     App::new()
@@ -37,6 +22,7 @@ fn main() {
         ))
         .init_state::<GameState>()
         .init_resource::<Paused>()
+        .insert_resource(WorldSelection::from_args())
         .insert_resource(ClearColor(Color::linear_rgb(0.3, 0.6, 0.9)))
         .run();
 }
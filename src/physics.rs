@@ -12,16 +12,49 @@ pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            apply_physics.run_if(in_state(GameState::Playing).and(is_unpaused)),
-        );
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .add_systems(
+                FixedUpdate,
+                apply_physics.run_if(in_state(GameState::Playing).and(is_unpaused)),
+            )
+            .add_systems(
+                RunFixedMainLoop,
+                interpolate_transforms
+                    .in_set(RunFixedMainLoopSystem::AfterFixedMainLoop)
+                    .run_if(is_unpaused),
+            );
     }
 }
 
+/// How many times a second physics and movement are stepped, independent of the frame rate.
+pub const TICK_RATE: f64 = 64.0;
+
 #[derive(Debug, Clone, Copy, Component)]
 pub struct Velocity(pub Vec3);
 
+/// Where a body is in the physics simulation, which only moves once per fixed step. Its
+/// [`Transform`] is interpolated between the last two steps, so that it moves smoothly at
+/// any frame rate.
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct PhysicalPosition {
+    pub current: Vec3,
+    pub previous: Vec3,
+}
+
+impl PhysicalPosition {
+    pub fn new(pos: Vec3) -> Self {
+        Self {
+            current: pos,
+            previous: pos,
+        }
+    }
+
+    /// Moves the body without interpolating from where it was.
+    pub fn teleport(&mut self, pos: Vec3) {
+        *self = Self::new(pos);
+    }
+}
+
 const GRAVITY: f32 = -24.0;
 const TERMINAL_VELOCITY: f32 = -78.4;
 const PLAYER_SIZE: Vec3 = Vec3::new(0.6, 1.8, 0.6);
@@ -143,24 +176,26 @@ fn clip_axis_internal(current_aabb: &Aabb, blocks: &[Aabb], mut delta: f32, axis
     delta
 }
 
-fn apply_physics(
+pub fn apply_physics(
     time: Res<Time>,
     level: Res<Level>,
-    mut query: Query<(&mut Transform, &mut Velocity, &mut Player)>,
+    mut query: Query<(&mut PhysicalPosition, &mut Velocity, &mut Player)>,
 ) {
     if time.elapsed_secs() < 0.25 {
         return;
     }
 
-    let (mut transform, mut velocity, mut player) = query.single_mut();
+    let (mut position, mut velocity, mut player) = query.single_mut();
     let dt = time.delta_secs();
 
-    // Calculate movement for this frame
+    position.previous = position.current;
+
+    // Calculate movement for this step
     let orig_movement = velocity.0 * dt;
     let mut movement = orig_movement;
 
     // Create player AABB
-    let mut player_aabb = Aabb::new(transform.translation, PLAYER_SIZE);
+    let mut player_aabb = Aabb::new(position.current, PLAYER_SIZE);
 
     // Get potential collisions
     let blocks = get_potential_collisions(&level, &player_aabb);
@@ -197,8 +232,7 @@ fn apply_physics(
         velocity.0.y = velocity.0.y.max(TERMINAL_VELOCITY);
     }
 
-    // Update transform position
-    transform.translation = player_aabb.center();
+    position.current = player_aabb.center();
 
     // Apply drag
     if player.on_ground {
@@ -209,3 +243,134 @@ fn apply_physics(
         velocity.0.z *= AIR_DRAG;
     }
 }
+
+/// Places bodies between their last two physics steps, by how far the next step has come.
+fn interpolate_transforms(
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<(&mut Transform, &PhysicalPosition)>,
+) {
+    let fraction = fixed_time.overstep_fraction();
+
+    for (mut transform, position) in &mut query {
+        transform.translation = position.previous.lerp(position.current, fraction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+
+    use super::*;
+    use crate::{
+        block::Block,
+        chunk::Chunk,
+        game_state::Paused,
+        position::{ChunkPos, LocalPos, CHUNK_INDICES},
+    };
+
+    /// Every physical position the player has been at, one per fixed step.
+    #[derive(Default, Resource)]
+    struct Path(Vec<Vec3>);
+
+    fn record_path(mut path: ResMut<Path>, query: Query<&PhysicalPosition>) {
+        path.0.push(query.single().current);
+    }
+
+    /// A floor four blocks deep, with a wall along one side for the player to run into.
+    fn level() -> Level {
+        let mut chunk = Chunk::new();
+
+        for index in 0..CHUNK_INDICES {
+            let pos = LocalPos::from_index(index);
+
+            if pos.y < 4 || (pos.x >= 7 && pos.y < 8) {
+                chunk.set(pos, Block::Rock);
+            }
+        }
+
+        let mut level = Level::new();
+        level.insert_chunk(ChunkPos::new(0, 0, 0), chunk);
+        level
+    }
+
+    fn app(frame_time: Duration) -> (App, Entity) {
+        let mut app = App::new();
+
+        app.add_plugins((MinimalPlugins, StatesPlugin, PhysicsPlugin))
+            .insert_state(GameState::Playing)
+            .init_resource::<Paused>()
+            .init_resource::<Path>()
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame_time))
+            .insert_resource(level())
+            .add_systems(FixedPostUpdate, record_path);
+
+        let start = Vec3::new(4.0, 10.0, 4.0);
+
+        let player = app
+            .world_mut()
+            .spawn((
+                Player::default(),
+                PhysicalPosition::new(start),
+                Transform::from_translation(start),
+                Velocity(Vec3::new(6.0, 3.0, 2.0)),
+            ))
+            .id();
+
+        (app, player)
+    }
+
+    fn simulate(frame_time: Duration, steps: usize) -> Vec<Vec3> {
+        let (mut app, _) = app(frame_time);
+
+        while app.world().resource::<Path>().0.len() < steps {
+            app.update();
+        }
+
+        let mut path = app.world_mut().remove_resource::<Path>().unwrap().0;
+        path.truncate(steps);
+        path
+    }
+
+    #[test]
+    fn same_path_at_any_frame_rate() {
+        let steps = 3 * TICK_RATE as usize;
+        let expected = simulate(Duration::from_secs_f64(1.0 / 60.0), steps);
+
+        // The player falls, lands on the floor and is stopped by the wall
+        let end = expected[steps - 1];
+        assert!((end.y - (4.0 + PLAYER_SIZE.y / 2.0)).abs() < 0.01);
+        assert!((end.x - (7.0 - PLAYER_SIZE.x / 2.0)).abs() < 0.01);
+
+        for fps in [20.0, 30.0, 144.0, 240.0, 1000.0] {
+            let path = simulate(Duration::from_secs_f64(1.0 / fps), steps);
+            assert_eq!(path, expected, "{fps} fps");
+        }
+    }
+
+    #[test]
+    fn transform_moves_smoothly_between_steps() {
+        // Four frames to every fixed step
+        let (mut app, player) = app(Duration::from_secs_f64(1.0 / (4.0 * TICK_RATE)));
+        let mut last = None;
+
+        for _ in 0..200 {
+            app.update();
+
+            let translation = app.world().get::<Transform>(player).unwrap().translation;
+            let position = app.world().get::<PhysicalPosition>(player).unwrap();
+
+            let min = position.previous.min(position.current);
+            let max = position.previous.max(position.current);
+            assert!(translation.cmpge(min).all() && translation.cmple(max).all());
+
+            // Still in the air, so it moves every frame and not just when a step runs
+            if app.world().resource::<Time<Fixed>>().elapsed_secs() > 0.3 {
+                assert_ne!(Some(translation), last);
+            }
+
+            last = Some(translation);
+        }
+    }
+}
@@ -3,9 +3,12 @@ mod movement;
 
 use bevy::prelude::*;
 use interaction::{break_or_place_block, update_focused_block, BlockBreakProgress, FocusedBlock};
-use movement::{player_look, player_move};
+use movement::{player_look, player_move, read_movement_input, MovementInput};
 
-use crate::{game_state::GameState, physics::Velocity};
+use crate::{
+    game_state::{is_unpaused, GameState},
+    physics::{apply_physics, PhysicalPosition, Velocity},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct PlayerPlugin;
@@ -19,12 +22,18 @@ impl Plugin for PlayerPlugin {
                 Update,
                 (
                     player_look,
-                    player_move,
+                    read_movement_input,
                     update_focused_block,
                     break_or_place_block,
                 )
                     .chain()
                     .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                player_move
+                    .before(apply_physics)
+                    .run_if(in_state(GameState::Playing).and(is_unpaused)),
             );
     }
 }
@@ -42,7 +51,9 @@ fn spawn_player(mut commands: Commands) {
         .spawn((
             Player::default(),
             Transform::from_xyz(0.0, 0.0, 0.0),
+            PhysicalPosition::default(),
             Velocity(Vec3::ZERO),
+            MovementInput::default(),
             Visibility::Inherited,
         ))
         .with_child((
@@ -7,9 +7,9 @@ use crate::{
     aabb::Aabb,
     block::Block,
     inventory::Inventory,
-    level::{Dirty, Level, Modified},
-    loader::VoxelMaterial,
-    position::{BlockPos, ChunkPos, LocalPos, CHUNK_SIZE},
+    level::{BlockChangeCause, Level},
+    loader::{ChunkMaterial, VoxelMaterial},
+    position::BlockPos,
     voxel_mesh::VoxelFace,
 };
 
@@ -48,7 +48,7 @@ pub fn update_focused_block(
     primary_window: Query<&Window, With<PrimaryWindow>>,
     camera_query: Query<(&Transform, &Parent), With<PlayerCamera>>,
     player_query: Query<&Transform, With<Player>>,
-    chunk_query: Query<(&ChunkPos, &MeshMaterial3d<VoxelMaterial>)>,
+    chunk_material: Res<ChunkMaterial>,
     mut materials: ResMut<Assets<VoxelMaterial>>,
 ) {
     let Ok(window) = primary_window.get_single() else {
@@ -71,23 +71,7 @@ pub fn update_focused_block(
         focused_block.air_pos = None;
         focused_block.face = None;
 
-        for (_, material) in chunk_query.iter() {
-            if !materials
-                .get(&material.0)
-                .unwrap()
-                .block_interaction
-                .is_set()
-            {
-                continue;
-            }
-
-            materials
-                .get_mut(&material.0)
-                .unwrap()
-                .block_interaction
-                .unset();
-        }
-
+        set_highlight(&mut materials, &chunk_material, None, 0);
         return;
     };
 
@@ -122,36 +106,38 @@ pub fn update_focused_block(
         focused_block.air_pos = None;
     }
 
-    let hit_chunk_pos = block_pos.chunk_pos();
-    let local_pos = block_pos.local_pos();
-
-    for (&chunk_pos, material) in chunk_query.iter() {
-        if chunk_pos != hit_chunk_pos
-            && !materials
-                .get(&material.0)
-                .unwrap()
-                .block_interaction
-                .is_set()
-        {
-            continue;
+    let break_stage = match break_progress.position {
+        Some(pos) if pos == block_pos => {
+            let stage = (break_progress.progress * BREAK_STAGES as f32).ceil() as u32;
+            stage.min(BREAK_STAGES) + 1
         }
+        _ => 1,
+    };
 
-        let material = materials.get_mut(&material.0).unwrap();
+    set_highlight(
+        &mut materials,
+        &chunk_material,
+        focused_block.block_pos,
+        break_stage,
+    );
+}
 
-        if chunk_pos == hit_chunk_pos && focused_block.block_pos.is_some() {
-            let break_stage = if let Some(pos) = break_progress.position {
-                if pos == block_pos {
-                    let stage = (break_progress.progress * BREAK_STAGES as f32).ceil() as u32;
-                    stage.min(BREAK_STAGES) + 1
-                } else {
-                    1
-                }
-            } else {
-                1
-            };
-            material.block_interaction.set(local_pos, face, break_stage);
-        } else {
-            material.block_interaction.unset();
+/// Points the chunk material's highlight at a block, only touching the material if it changed
+/// so that it isn't uploaded again every frame.
+fn set_highlight(
+    materials: &mut Assets<VoxelMaterial>,
+    chunk_material: &ChunkMaterial,
+    pos: Option<BlockPos>,
+    value: u32,
+) {
+    let highlight = VoxelMaterial::highlight(pos, value);
+
+    if materials
+        .get(&chunk_material.0)
+        .is_some_and(|material| material.highlight != highlight)
+    {
+        if let Some(material) = materials.get_mut(&chunk_material.0) {
+            material.highlight = highlight;
         }
     }
 }
@@ -189,23 +175,17 @@ pub fn break_or_place_block(
             break_progress.progress += time.delta_secs() / BLOCK_BREAK_TIME;
 
             if break_progress.progress >= 1.0 {
-                let chunk_pos = block_pos.chunk_pos();
-                let local_pos = block_pos.local_pos();
-
-                for drop in level.block(block_pos).drops() {
+                let broken = level.set_block(
+                    &mut commands,
+                    block_pos,
+                    Block::Air,
+                    BlockChangeCause::Broken,
+                );
+
+                for drop in broken.iter().flat_map(|block| block.drops()) {
                     inventory.add(drop);
                 }
 
-                if let Some(chunk) = level.chunk_mut(chunk_pos) {
-                    chunk.set(local_pos, Block::Air);
-                }
-
-                if let Some(entity) = level.entity(chunk_pos) {
-                    commands.entity(entity).insert((Modified, Dirty));
-                }
-
-                update_neighbor_chunks(&level, &mut commands, chunk_pos, local_pos);
-
                 break_progress.position = None;
                 break_progress.progress = 0.0;
             }
@@ -217,18 +197,12 @@ pub fn break_or_place_block(
 
     if mouse.just_pressed(MouseButton::Right) {
         if let Some(air_pos) = focused_block.air_pos {
-            let chunk_pos = air_pos.chunk_pos();
-            let local_pos = air_pos.local_pos();
-
-            if let Some(chunk) = level.chunk_mut(chunk_pos) {
-                chunk.set(local_pos, Block::Rock);
-            }
-
-            if let Some(entity) = level.entity(chunk_pos) {
-                commands.entity(entity).insert((Modified, Dirty));
-            }
-
-            update_neighbor_chunks(&level, &mut commands, chunk_pos, local_pos);
+            level.set_block(
+                &mut commands,
+                air_pos,
+                Block::Rock,
+                BlockChangeCause::Placed,
+            );
         }
     }
 }
@@ -279,34 +253,3 @@ fn raycast_blocks(
 
     None
 }
-
-fn update_neighbor_chunks(
-    level: &Level,
-    commands: &mut Commands,
-    chunk_pos: ChunkPos,
-    local_pos: LocalPos,
-) {
-    let neighbors = [
-        ChunkPos::X,
-        ChunkPos::NEG_X,
-        ChunkPos::Y,
-        ChunkPos::NEG_Y,
-        ChunkPos::Z,
-        ChunkPos::NEG_Z,
-    ];
-
-    for &offset in &neighbors {
-        if local_pos.x == 0
-            || local_pos.x == CHUNK_SIZE - 1
-            || local_pos.y == 0
-            || local_pos.y == CHUNK_SIZE - 1
-            || local_pos.z == 0
-            || local_pos.z == CHUNK_SIZE - 1
-        {
-            let neighbor_pos = chunk_pos + offset;
-            if let Some(entity) = level.entity(neighbor_pos) {
-                commands.entity(entity).insert((Modified, Dirty));
-            }
-        }
-    }
-}
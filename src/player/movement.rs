@@ -4,71 +4,97 @@ use bevy::{
     window::{CursorGrabMode, PrimaryWindow},
 };
 
-use crate::physics::Velocity;
+use crate::physics::{PhysicalPosition, Velocity};
 
 use super::{Player, PlayerCamera};
 
-pub fn player_move(
+/// The movement the player is asking for, held until the next fixed step. A jump stays asked
+/// for until a step has seen it, so that tapping the jump key between steps isn't lost.
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct MovementInput {
+    /// Normalized horizontal direction to walk in.
+    pub direction: Vec3,
+    pub jump: bool,
+}
+
+/// Reads the movement keys every frame, for [`player_move`] to act on at the next fixed step.
+pub fn read_movement_input(
     keys: Res<ButtonInput<KeyCode>>,
-    time: Res<Time>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
-    mut query: Query<(&mut Velocity, &mut Transform, &Player)>,
+    mut query: Query<(&mut MovementInput, &mut PhysicalPosition, &mut Transform), With<Player>>,
     camera: Query<&Transform, (With<PlayerCamera>, Without<Player>)>,
 ) {
-    const MOVEMENT_SPEED: f32 = 20.0;
-    const JUMP_FORCE: f32 = 7.6;
+    let Ok(window) = primary_window.get_single() else {
+        return;
+    };
 
-    if let Ok(window) = primary_window.get_single() {
-        let (mut velocity, mut p_transform, physics) = query.single_mut();
-        let transform = camera.single();
-        let mut movement = Vec3::ZERO;
+    let (mut input, mut position, mut p_transform) = query.single_mut();
+    let transform = camera.single();
+    let mut movement = Vec3::ZERO;
 
-        if keys.just_pressed(KeyCode::KeyJ) {
-            p_transform.translation.x += 10000.0;
-        }
+    if keys.just_pressed(KeyCode::KeyJ) {
+        let pos = position.current + Vec3::new(10000.0, 0.0, 0.0);
+        position.teleport(pos);
+        p_transform.translation = pos;
+    }
 
-        // Get the camera's forward and right vectors
-        let forward = transform.forward();
-        let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
-        let right = Vec3::new(-forward.z, 0.0, forward.x);
-
-        if window.cursor_options.grab_mode != CursorGrabMode::None {
-            if keys.pressed(KeyCode::KeyW) {
-                movement += forward;
-            }
-            if keys.pressed(KeyCode::KeyS) {
-                movement -= forward;
-            }
-            if keys.pressed(KeyCode::KeyA) {
-                movement -= right;
-            }
-            if keys.pressed(KeyCode::KeyD) {
-                movement += right;
-            }
-
-            // Jump when space is pressed and on ground
-            if keys.pressed(KeyCode::Space) && physics.on_ground {
-                velocity.0.y = JUMP_FORCE;
-            }
+    // Get the camera's forward and right vectors
+    let forward = transform.forward();
+    let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+    let right = Vec3::new(-forward.z, 0.0, forward.x);
+
+    let grabbed = window.cursor_options.grab_mode != CursorGrabMode::None;
+
+    if grabbed {
+        if keys.pressed(KeyCode::KeyW) {
+            movement += forward;
+        }
+        if keys.pressed(KeyCode::KeyS) {
+            movement -= forward;
         }
+        if keys.pressed(KeyCode::KeyA) {
+            movement -= right;
+        }
+        if keys.pressed(KeyCode::KeyD) {
+            movement += right;
+        }
+    }
 
-        // Normalize horizontal movement
-        movement = movement.normalize_or_zero();
-
-        // Apply movement
-        let target_velocity = movement * MOVEMENT_SPEED;
-
-        // Smoothly interpolate horizontal velocity
-        let acceleration = if physics.on_ground { 10.0 } else { 2.0 };
-        velocity.0.x = velocity
-            .0
-            .x
-            .lerp(target_velocity.x, acceleration * time.delta_secs());
-        velocity.0.z = velocity
-            .0
-            .z
-            .lerp(target_velocity.z, acceleration * time.delta_secs());
+    // Normalize horizontal movement
+    input.direction = movement.normalize_or_zero();
+    input.jump |= grabbed && (keys.pressed(KeyCode::Space) || keys.just_pressed(KeyCode::Space));
+}
+
+pub fn player_move(
+    time: Res<Time>,
+    mut query: Query<(&mut Velocity, &mut MovementInput, &Player)>,
+) {
+    const MOVEMENT_SPEED: f32 = 20.0;
+    const JUMP_FORCE: f32 = 7.6;
+
+    let (mut velocity, mut input, physics) = query.single_mut();
+
+    // Jump when space is pressed and on ground
+    if input.jump && physics.on_ground {
+        velocity.0.y = JUMP_FORCE;
     }
+
+    // Holding the key asks again on the next frame
+    input.jump = false;
+
+    // Apply movement
+    let target_velocity = input.direction * MOVEMENT_SPEED;
+
+    // Smoothly interpolate horizontal velocity
+    let acceleration = if physics.on_ground { 10.0 } else { 2.0 };
+    velocity.0.x = velocity
+        .0
+        .x
+        .lerp(target_velocity.x, acceleration * time.delta_secs());
+    velocity.0.z = velocity
+        .0
+        .z
+        .lerp(target_velocity.z, acceleration * time.delta_secs());
 }
 
 pub fn player_look(
@@ -2,6 +2,7 @@ mod hud;
 mod inventory_menu;
 mod item_image_cache;
 mod pause_menu;
+mod warnings;
 
 use std::mem;
 
@@ -12,13 +13,19 @@ use bevy::{
     window::{CursorGrabMode, PrimaryWindow},
 };
 use hud::{
-    set_hotbar_slot, spawn_hud, update_fps_text, update_hotbar_display, update_position_text,
+    set_hotbar_slot, spawn_hud, update_chunk_loading_text, update_fps_text, update_hotbar_display,
+    update_position_text,
 };
 use inventory_menu::{
     clear_hotbar_slot, set_hotbar_selection, setup_inventory_menu, toggle_inventory_menu,
     update_inventory_menu, update_item_hover,
 };
-use pause_menu::{setup_pause_menu, toggle_pause_menu};
+use pause_menu::{
+    cancel_restore_on_click, change_render_distance_on_click, confirm_restore_on_click,
+    restore_backup_on_click, setup_pause_menu, toggle_pause_menu, update_backup_list,
+    update_menu_button_hover, update_render_distance_text,
+};
+use warnings::{expire_warnings, show_persistence_warnings, spawn_warning_list};
 
 use crate::{
     game_state::{is_unpaused, GameState},
@@ -33,6 +40,7 @@ pub struct UiPlugin;
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Inventory>()
+            .add_systems(Startup, spawn_warning_list)
             .add_systems(OnEnter(GameState::Setup), initial_grab_cursor)
             .add_systems(
                 OnEnter(GameState::Playing),
@@ -43,6 +51,7 @@ impl Plugin for UiPlugin {
                 (
                     update_position_text,
                     update_fps_text,
+                    update_chunk_loading_text,
                     (update_hotbar_display, set_hotbar_slot).chain(),
                 )
                     .run_if(in_state(GameState::Playing).and(is_unpaused)),
@@ -51,6 +60,11 @@ impl Plugin for UiPlugin {
                 Update,
                 (
                     toggle_pause_menu,
+                    (
+                        update_backup_list,
+                        update_render_distance_text,
+                        update_menu_button_hover,
+                    ),
                     (
                         toggle_inventory_menu,
                         update_inventory_menu,
@@ -63,8 +77,18 @@ impl Plugin for UiPlugin {
                     .chain()
                     .run_if(in_state(GameState::Playing)),
             )
-            .add_systems(Update, update_scroll_position)
-            .add_observer(set_hotbar_selection);
+            .add_systems(
+                Update,
+                (
+                    update_scroll_position,
+                    (show_persistence_warnings, expire_warnings).chain(),
+                ),
+            )
+            .add_observer(set_hotbar_selection)
+            .add_observer(restore_backup_on_click)
+            .add_observer(confirm_restore_on_click)
+            .add_observer(cancel_restore_on_click)
+            .add_observer(change_render_distance_on_click);
     }
 }
 
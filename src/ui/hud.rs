@@ -1,6 +1,12 @@
 use bevy::prelude::*;
 
-use crate::{inventory::Inventory, loader::ItemImages, player::Player, position::BlockPos};
+use crate::{
+    inventory::Inventory,
+    level::{generator::LevelGenerator, ChunkLoadingStats},
+    loader::ItemImages,
+    player::Player,
+    position::BlockPos,
+};
 
 use super::ItemImageCache;
 
@@ -13,6 +19,9 @@ pub struct PositionText;
 #[derive(Debug, Clone, Copy, Component)]
 pub struct FpsText;
 
+#[derive(Debug, Clone, Copy, Component)]
+pub struct ChunkLoadingText;
+
 #[derive(Debug, Clone, Copy, Component)]
 pub struct HotbarSlot(usize);
 
@@ -56,6 +65,17 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
                 },
             ));
 
+            hud.spawn((
+                ChunkLoadingText,
+                Text::new("Chunks: 0/s"),
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(5.0),
+                    top: Val::Px(45.0),
+                    ..default()
+                },
+            ));
+
             hud.spawn((
                 Text::new("+"),
                 TextFont {
@@ -102,14 +122,16 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
 }
 
 pub fn update_position_text(
+    generator: Res<LevelGenerator>,
     player_query: Query<&Transform, With<Player>>,
     mut text_query: Query<&mut Text, With<PositionText>>,
 ) {
     let player_transform = player_query.single();
     let pos = BlockPos::from_world(player_transform.translation);
+    let biome = generator.biome(pos.x, pos.z);
 
     let mut text = text_query.single_mut();
-    text.0 = format!("{}, {}, {}", pos.x, pos.y, pos.z);
+    text.0 = format!("{}, {}, {} ({})", pos.x, pos.y, pos.z, biome.name);
 }
 
 pub fn update_fps_text(time: Res<Time>, mut text_query: Query<&mut Text, With<FpsText>>) {
@@ -118,6 +140,17 @@ pub fn update_fps_text(time: Res<Time>, mut text_query: Query<&mut Text, With<Fp
     text.0 = format!("FPS: {:.1}", fps);
 }
 
+pub fn update_chunk_loading_text(
+    stats: Res<ChunkLoadingStats>,
+    mut text_query: Query<&mut Text, With<ChunkLoadingText>>,
+) {
+    let mut text = text_query.single_mut();
+    text.0 = format!(
+        "Chunks: {:.0}/s, {} queued, {} loading",
+        stats.chunks_per_second, stats.queue_depth, stats.in_flight
+    );
+}
+
 pub fn update_hotbar_display(
     mut commands: Commands,
     inventory: Res<Inventory>,
@@ -1,6 +1,15 @@
+use std::path::PathBuf;
+
 use bevy::{prelude::*, window::PrimaryWindow};
 
-use crate::game_state::Paused;
+use crate::{
+    game_state::Paused,
+    level::{
+        backup::{backups_dir, list_backups, RestoreBackup},
+        world::unix_time,
+        RenderDistance, WorldSelection,
+    },
+};
 
 use super::{hud::Hud, inventory_menu::InventoryMenu, set_grab};
 
@@ -8,6 +17,37 @@ use super::{hud::Hud, inventory_menu::InventoryMenu, set_grab};
 #[require(Node(pause_menu_node), BackgroundColor(pause_menu_bg))]
 pub struct PauseMenu;
 
+#[derive(Debug, Clone, Copy, Component)]
+pub struct BackupList;
+
+/// A button in the pause menu, which lights up when hovered.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct MenuButton;
+
+#[derive(Debug, Clone, Component)]
+pub struct RestoreButton {
+    path: PathBuf,
+}
+
+/// Asks before restoring the backup that was clicked, since it replaces the world.
+#[derive(Debug, Clone, Default, Component)]
+pub struct RestoreConfirmation {
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct ConfirmRestoreButton;
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct CancelRestoreButton;
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct RenderDistanceText;
+
+/// Changes the render distance by this many chunks when clicked.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct RenderDistanceButton(i32);
+
 fn pause_menu_node() -> Node {
     Node {
         position_type: PositionType::Absolute,
@@ -15,6 +55,11 @@ fn pause_menu_node() -> Node {
         left: Val::Px(0.0),
         width: Val::Percent(100.0),
         height: Val::Percent(100.0),
+        display: Display::Flex,
+        flex_direction: FlexDirection::Column,
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::Center,
+        row_gap: Val::Px(8.0),
         ..default()
     }
 }
@@ -26,7 +71,278 @@ fn pause_menu_bg() -> BackgroundColor {
 pub fn setup_pause_menu(mut commands: Commands) {
     commands
         .spawn((PauseMenu, Visibility::Hidden))
-        .with_children(|_menu| {});
+        .with_children(|menu| {
+            menu.spawn(Node {
+                display: Display::Flex,
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.0),
+                margin: UiRect::bottom(Val::Px(16.0)),
+                ..default()
+            })
+            .with_children(|row| {
+                spawn_render_distance_button(row, "-", -1);
+
+                row.spawn((
+                    RenderDistanceText,
+                    Text::new(""),
+                    Node {
+                        width: Val::Px(220.0),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    TextLayout::new_with_justify(JustifyText::Center),
+                ));
+
+                spawn_render_distance_button(row, "+", 1);
+            });
+
+            menu.spawn((
+                Text::new("Restore a backup"),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+            ));
+
+            menu.spawn((
+                RestoreConfirmation::default(),
+                Node {
+                    display: Display::None,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.0),
+                    ..default()
+                },
+            ))
+            .with_children(|confirmation| {
+                confirmation.spawn(Text::new(
+                    "Restore this backup? The world as it is now will be backed up first.",
+                ));
+
+                confirmation
+                    .spawn(Node {
+                        display: Display::Flex,
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(8.0),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        spawn_menu_button(row, ConfirmRestoreButton, "Restore");
+                        spawn_menu_button(row, CancelRestoreButton, "Cancel");
+                    });
+            });
+
+            menu.spawn((
+                BackupList,
+                Node {
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Stretch,
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn spawn_render_distance_button(parent: &mut ChildBuilder, label: &str, change: i32) {
+    parent
+        .spawn((
+            RenderDistanceButton(change),
+            MenuButton,
+            Button,
+            Node {
+                width: Val::Px(32.0),
+                height: Val::Px(32.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+            BorderRadius::all(Val::Px(4.0)),
+        ))
+        .with_child((Text::new(label), PickingBehavior::IGNORE));
+}
+
+fn spawn_menu_button(parent: &mut ChildBuilder, marker: impl Component, label: &str) {
+    parent
+        .spawn((
+            marker,
+            MenuButton,
+            Button,
+            Node {
+                padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+            BorderRadius::all(Val::Px(4.0)),
+        ))
+        .with_child((Text::new(label), PickingBehavior::IGNORE));
+}
+
+pub fn update_render_distance_text(
+    render_distance: Res<RenderDistance>,
+    mut text: Query<&mut Text, With<RenderDistanceText>>,
+) {
+    if !render_distance.is_changed() {
+        return;
+    }
+
+    for mut text in text.iter_mut() {
+        text.0 = format!("Render distance: {} chunks", render_distance.chunks);
+    }
+}
+
+pub fn change_render_distance_on_click(
+    click: Trigger<Pointer<Click>>,
+    mut render_distance: ResMut<RenderDistance>,
+    query: Query<&RenderDistanceButton>,
+) {
+    let Ok(button) = query.get(click.entity()) else {
+        return;
+    };
+
+    let chunks = render_distance.chunks + button.0;
+    render_distance.set_chunks(chunks);
+}
+
+/// Lists the backups whenever the pause menu is opened, since they're made in the background.
+pub fn update_backup_list(
+    mut commands: Commands,
+    paused: Res<Paused>,
+    selection: Res<WorldSelection>,
+    backup_list: Query<Entity, With<BackupList>>,
+    mut confirmation: Query<(&mut RestoreConfirmation, &mut Node)>,
+) {
+    if !paused.is_changed() || !paused.0 {
+        return;
+    }
+
+    for (mut confirmation, mut node) in confirmation.iter_mut() {
+        confirmation.path = None;
+        node.display = Display::None;
+    }
+
+    let Ok(backup_list) = backup_list.get_single() else {
+        return;
+    };
+
+    let backups = match list_backups(&backups_dir(&selection.dir())) {
+        Ok(backups) => backups,
+        Err(error) => {
+            error!("Failed to list backups: {error}");
+            Vec::new()
+        }
+    };
+
+    let now = unix_time();
+
+    commands
+        .entity(backup_list)
+        .despawn_descendants()
+        .with_children(|list| {
+            if backups.is_empty() {
+                list.spawn(Text::new("No backups yet"));
+            }
+
+            for backup in backups {
+                list.spawn((
+                    RestoreButton { path: backup.path },
+                    MenuButton,
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                    BorderRadius::all(Val::Px(4.0)),
+                ))
+                .with_child((
+                    Text::new(format!("From {} ago", format_age(now - backup.created_at))),
+                    PickingBehavior::IGNORE,
+                ));
+            }
+        });
+}
+
+pub fn update_menu_button_hover(
+    mut query: Query<(&Interaction, &mut BackgroundColor), With<MenuButton>>,
+) {
+    for (interaction, mut color) in query.iter_mut() {
+        color.0 = match interaction {
+            Interaction::Hovered => Color::srgb(0.35, 0.35, 0.35),
+            Interaction::Pressed => Color::srgb(0.2, 0.2, 0.2),
+            Interaction::None => Color::srgb(0.25, 0.25, 0.25),
+        };
+    }
+}
+
+pub fn restore_backup_on_click(
+    click: Trigger<Pointer<Click>>,
+    query: Query<&RestoreButton>,
+    mut confirmation: Query<(&mut RestoreConfirmation, &mut Node)>,
+) {
+    let Ok(button) = query.get(click.entity()) else {
+        return;
+    };
+
+    for (mut confirmation, mut node) in confirmation.iter_mut() {
+        confirmation.path = Some(button.path.clone());
+        node.display = Display::Flex;
+    }
+}
+
+pub fn confirm_restore_on_click(
+    click: Trigger<Pointer<Click>>,
+    mut restore_events: EventWriter<RestoreBackup>,
+    query: Query<(), With<ConfirmRestoreButton>>,
+    mut confirmation: Query<(&mut RestoreConfirmation, &mut Node)>,
+) {
+    if !query.contains(click.entity()) {
+        return;
+    }
+
+    for (mut confirmation, mut node) in confirmation.iter_mut() {
+        node.display = Display::None;
+
+        if let Some(path) = confirmation.path.take() {
+            restore_events.send(RestoreBackup { path });
+        }
+    }
+}
+
+pub fn cancel_restore_on_click(
+    click: Trigger<Pointer<Click>>,
+    query: Query<(), With<CancelRestoreButton>>,
+    mut confirmation: Query<(&mut RestoreConfirmation, &mut Node)>,
+) {
+    if !query.contains(click.entity()) {
+        return;
+    }
+
+    for (mut confirmation, mut node) in confirmation.iter_mut() {
+        confirmation.path = None;
+        node.display = Display::None;
+    }
+}
+
+fn format_age(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    let hours = minutes / 60;
+    let days = hours / 24;
+
+    if days > 0 {
+        format!("{days} days")
+    } else if hours > 0 {
+        format!("{hours} hours")
+    } else if minutes > 0 {
+        format!("{minutes} minutes")
+    } else {
+        format!("{} seconds", seconds.max(0))
+    }
 }
 
 pub fn toggle_pause_menu(
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::level::PersistenceError;
+
+const WARNING_DURATION: f32 = 10.0;
+const MAX_WARNINGS: usize = 5;
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct WarningList;
+
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Warning {
+    expires_at: f32,
+}
+
+pub fn spawn_warning_list(mut commands: Commands) {
+    commands.spawn((
+        WarningList,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            max_width: Val::Px(500.0),
+            display: Display::Flex,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::End,
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        GlobalZIndex(10),
+        PickingBehavior::IGNORE,
+    ));
+}
+
+pub fn show_persistence_warnings(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut errors: EventReader<PersistenceError>,
+    warning_list: Query<Entity, With<WarningList>>,
+    warnings: Query<(Entity, &Warning)>,
+) {
+    let Ok(warning_list) = warning_list.get_single() else {
+        return;
+    };
+
+    // Every warning in the order they expire, including the ones added below, so that each new
+    // warning makes room by removing the one that would expire first
+    let mut shown: Vec<(Entity, &Warning)> = warnings.iter().collect();
+    shown.sort_by(|(_, a), (_, b)| a.expires_at.total_cmp(&b.expires_at));
+    let mut shown: VecDeque<Entity> = shown.into_iter().map(|(entity, _)| entity).collect();
+
+    for error in errors.read() {
+        if shown.len() >= MAX_WARNINGS {
+            if let Some(oldest) = shown.pop_front() {
+                commands.entity(oldest).despawn_recursive();
+            }
+        }
+
+        let warning = commands
+            .spawn((
+                Warning {
+                    expires_at: time.elapsed_secs() + WARNING_DURATION,
+                },
+                Text::new(error.to_string()),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(1.0, 0.8, 0.3)),
+                Node {
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+                BorderRadius::all(Val::Px(4.0)),
+            ))
+            .set_parent(warning_list)
+            .id();
+
+        shown.push_back(warning);
+    }
+}
+
+pub fn expire_warnings(
+    mut commands: Commands,
+    time: Res<Time>,
+    warnings: Query<(Entity, &Warning)>,
+) {
+    for (entity, warning) in warnings.iter() {
+        if warning.expires_at <= time.elapsed_secs() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
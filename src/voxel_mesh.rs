@@ -8,29 +8,10 @@ use bevy::{
 };
 
 use crate::{
-    level::Level,
-    position::{BlockPos, LocalPos},
+    chunk::ChunkSnapshot,
+    position::{BlockPos, CHUNK_SIZE},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub enum VoxelCorner {
-    TopLeft,
-    TopRight,
-    BottomLeft,
-    BottomRight,
-}
-
-impl VoxelCorner {
-    fn to_index(self) -> u32 {
-        match self {
-            Self::TopLeft => 0,
-            Self::TopRight => 1,
-            Self::BottomLeft => 2,
-            Self::BottomRight => 3,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VoxelFace {
     Top,
@@ -41,14 +22,91 @@ pub enum VoxelFace {
     Back,
 }
 
+impl VoxelFace {
+    pub const ALL: [Self; 6] = [
+        Self::Top,
+        Self::Bottom,
+        Self::Left,
+        Self::Right,
+        Self::Front,
+        Self::Back,
+    ];
+
+    /// The axis the face points along, followed by the two axes across it, as indices into
+    /// `[x, y, z]`. Quads are laid out along the second axis first.
+    pub fn axes(self) -> [usize; 3] {
+        match self {
+            Self::Top | Self::Bottom => [1, 0, 2],
+            Self::Left | Self::Right => [0, 2, 1],
+            Self::Front | Self::Back => [2, 0, 1],
+        }
+    }
+
+    /// The offset to the block this face looks out onto.
+    pub fn normal(self) -> BlockPos {
+        match self {
+            Self::Top => BlockPos::Y,
+            Self::Bottom => BlockPos::NEG_Y,
+            Self::Left => BlockPos::NEG_X,
+            Self::Right => BlockPos::X,
+            Self::Front => BlockPos::Z,
+            Self::Back => BlockPos::NEG_Z,
+        }
+    }
+
+    fn is_positive(self) -> bool {
+        matches!(self, Self::Top | Self::Right | Self::Front)
+    }
+
+    /// The corners of a quad in the order they're emitted, as steps along the two axes across
+    /// the face, and whether the triangles are wound in that order or against it.
+    fn corners(self) -> ([[u32; 2]; 4], bool) {
+        match self {
+            Self::Top | Self::Bottom => ([[0, 1], [1, 1], [1, 0], [0, 0]], self == Self::Top),
+            Self::Left | Self::Front => ([[0, 1], [0, 0], [1, 0], [1, 1]], true),
+            Self::Right | Self::Back => ([[0, 1], [0, 0], [1, 0], [1, 1]], false),
+        }
+    }
+}
+
+/// How faces are turned into quads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// One quad for every visible block face.
+    PerFace,
+    /// Neighboring faces that look the same are merged into larger quads.
+    #[default]
+    Greedy,
+}
+
+/// What a visible block face looks like. Faces can only be merged if they look the same, and
+/// have the same ambient occlusion at every corner, so the shading doesn't change across them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceAppearance {
+    pub tex_index: u32,
+    pub ao: [u32; 4],
+}
+
+impl FaceAppearance {
+    fn is_mergeable(self) -> bool {
+        self.ao.iter().all(|&ao| ao == self.ao[0])
+    }
+}
+
+/// The faces in one layer of a chunk facing the same way, indexed by their position along
+/// the two axes across the face as `b * CHUNK_SIZE + a`.
+pub type FaceLayer = [Option<FaceAppearance>; CHUNK_SIZE * CHUNK_SIZE];
+
 #[derive(Debug, Default, Clone)]
 pub struct VoxelMesh {
     voxels: Vec<u32>,
     indices: Vec<u32>,
-    positions: Vec<Vec3>,
 }
 
 impl VoxelMesh {
+    /// Each vertex is packed into a `u32`: 5 bits each for its x, y and z position within the
+    /// chunk (from 0 to 16 inclusive), 3 for the face, 2 for ambient occlusion, and 12 for the
+    /// texture index.
     pub const VOXEL: MeshVertexAttribute =
         MeshVertexAttribute::new("Vertex_Voxel", 0, VertexFormat::Uint32);
 
@@ -56,81 +114,104 @@ impl VoxelMesh {
         Self::default()
     }
 
-    pub fn add_vertex(
-        &mut self,
-        pos: LocalPos,
-        corner: VoxelCorner,
-        face: VoxelFace,
-        tex_index: u32,
-        ao: u32,
-    ) -> u32 {
-        self.positions
-            .push(Vec3::new(pos.x as f32, pos.y as f32, pos.z as f32));
-
-        let x = (pos.x as u32 & 0x0F) << 28;
-        let y = (pos.y as u32 & 0x0F) << 24;
-        let z = (pos.z as u32 & 0x0F) << 20;
-        let corner = (corner.to_index()) << 18;
-        let face = (face as u32) << 15;
-        let ao = (ao & 0x3) << 13; // Pack AO into bits 13-14
-        let tex_index = tex_index & 0x1FFF; // Reduced to 13 bits to make room for AO
-        let voxel = x | y | z | corner | face | ao | tex_index;
+    pub fn vertex_count(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn add_vertex(&mut self, pos: UVec3, face: VoxelFace, tex_index: u32, ao: u32) -> u32 {
+        let x = (pos.x & 0x1F) << 27;
+        let y = (pos.y & 0x1F) << 22;
+        let z = (pos.z & 0x1F) << 17;
+        let face = (face as u32) << 14;
+        let ao = (ao & 0x3) << 12;
+        let tex_index = tex_index & 0xFFF;
+        let voxel = x | y | z | face | ao | tex_index;
         self.voxels.push(voxel);
         self.voxels.len() as u32 - 1
     }
 
-    pub fn render_face(
+    /// Adds a quad covering `size` faces from `start`, both given along the two axes across
+    /// the face, in the given layer of the chunk.
+    pub fn add_quad(
         &mut self,
-        level: &Level,
-        block_pos: BlockPos,
         face: VoxelFace,
-        tex_index: u32,
+        layer: u32,
+        start: UVec2,
+        size: UVec2,
+        appearance: FaceAppearance,
     ) {
-        let pos = block_pos.local_pos();
-        let ao = self.ambient_occlusion(level, block_pos, face);
-
-        match face {
-            VoxelFace::Top => {
-                let a = self.add_vertex(pos, VoxelCorner::BottomLeft, face, tex_index, ao[0]);
-                let b = self.add_vertex(pos, VoxelCorner::BottomRight, face, tex_index, ao[1]);
-                let c = self.add_vertex(pos, VoxelCorner::TopRight, face, tex_index, ao[2]);
-                let d = self.add_vertex(pos, VoxelCorner::TopLeft, face, tex_index, ao[3]);
-                self.add_indices([a, b, c, a, c, d]);
-            }
-            VoxelFace::Bottom => {
-                let a = self.add_vertex(pos, VoxelCorner::BottomLeft, face, tex_index, ao[0]);
-                let b = self.add_vertex(pos, VoxelCorner::BottomRight, face, tex_index, ao[1]);
-                let c = self.add_vertex(pos, VoxelCorner::TopRight, face, tex_index, ao[2]);
-                let d = self.add_vertex(pos, VoxelCorner::TopLeft, face, tex_index, ao[3]);
-                self.add_indices([a, c, b, a, d, c]);
-            }
-            VoxelFace::Left => {
-                let a = self.add_vertex(pos, VoxelCorner::BottomLeft, face, tex_index, ao[0]);
-                let b = self.add_vertex(pos, VoxelCorner::TopLeft, face, tex_index, ao[1]);
-                let c = self.add_vertex(pos, VoxelCorner::TopRight, face, tex_index, ao[2]);
-                let d = self.add_vertex(pos, VoxelCorner::BottomRight, face, tex_index, ao[3]);
-                self.add_indices([a, b, c, a, c, d]);
-            }
-            VoxelFace::Right => {
-                let a = self.add_vertex(pos, VoxelCorner::BottomLeft, face, tex_index, ao[0]);
-                let b = self.add_vertex(pos, VoxelCorner::TopLeft, face, tex_index, ao[1]);
-                let c = self.add_vertex(pos, VoxelCorner::TopRight, face, tex_index, ao[2]);
-                let d = self.add_vertex(pos, VoxelCorner::BottomRight, face, tex_index, ao[3]);
-                self.add_indices([a, c, b, a, d, c]);
-            }
-            VoxelFace::Front => {
-                let a = self.add_vertex(pos, VoxelCorner::BottomLeft, face, tex_index, ao[0]);
-                let b = self.add_vertex(pos, VoxelCorner::TopLeft, face, tex_index, ao[1]);
-                let c = self.add_vertex(pos, VoxelCorner::TopRight, face, tex_index, ao[2]);
-                let d = self.add_vertex(pos, VoxelCorner::BottomRight, face, tex_index, ao[3]);
-                self.add_indices([a, b, c, a, c, d]);
-            }
-            VoxelFace::Back => {
-                let a = self.add_vertex(pos, VoxelCorner::BottomLeft, face, tex_index, ao[0]);
-                let b = self.add_vertex(pos, VoxelCorner::TopLeft, face, tex_index, ao[1]);
-                let c = self.add_vertex(pos, VoxelCorner::TopRight, face, tex_index, ao[2]);
-                let d = self.add_vertex(pos, VoxelCorner::BottomRight, face, tex_index, ao[3]);
-                self.add_indices([a, c, b, a, d, c]);
+        let [normal, axis_a, axis_b] = face.axes();
+        let (corners, forward) = face.corners();
+
+        let [a, b, c, d] = [0, 1, 2, 3].map(|i| {
+            let [step_a, step_b] = corners[i];
+            let mut pos = [0; 3];
+            pos[normal] = layer + u32::from(face.is_positive());
+            pos[axis_a] = start.x + step_a * size.x;
+            pos[axis_b] = start.y + step_b * size.y;
+
+            self.add_vertex(
+                UVec3::from(pos),
+                face,
+                appearance.tex_index,
+                appearance.ao[i],
+            )
+        });
+
+        if forward {
+            self.add_indices([a, b, c, a, c, d]);
+        } else {
+            self.add_indices([a, c, b, a, d, c]);
+        }
+    }
+
+    /// Adds quads for a layer of faces, merging them first if greedy meshing is enabled.
+    pub fn add_layer(
+        &mut self,
+        face: VoxelFace,
+        layer: u32,
+        faces: &mut FaceLayer,
+        mode: MeshingMode,
+    ) {
+        let index = |a: usize, b: usize| b * CHUNK_SIZE + a;
+
+        for b in 0..CHUNK_SIZE {
+            let mut a = 0;
+
+            while a < CHUNK_SIZE {
+                let Some(appearance) = faces[index(a, b)] else {
+                    a += 1;
+                    continue;
+                };
+
+                let mut width = 1;
+                let mut height = 1;
+
+                if mode == MeshingMode::Greedy && appearance.is_mergeable() {
+                    while a + width < CHUNK_SIZE && faces[index(a + width, b)] == Some(appearance) {
+                        width += 1;
+                    }
+
+                    while b + height < CHUNK_SIZE
+                        && (a..a + width).all(|a| faces[index(a, b + height)] == Some(appearance))
+                    {
+                        height += 1;
+                    }
+                }
+
+                for b in b..b + height {
+                    faces[index(a, b)..index(a + width, b)].fill(None);
+                }
+
+                self.add_quad(
+                    face,
+                    layer,
+                    UVec2::new(a as u32, b as u32),
+                    UVec2::new(width as u32, height as u32),
+                    appearance,
+                );
+
+                a += width;
             }
         }
     }
@@ -145,142 +226,148 @@ impl VoxelMesh {
         mesh.insert_indices(Indices::U32(self.indices.clone()));
         mesh
     }
+}
 
-    fn ambient_occlusion(&self, level: &Level, block_pos: BlockPos, face: VoxelFace) -> [u32; 4] {
-        match face {
-            VoxelFace::Top => {
-                let top = block_pos.top();
-
-                let [s1, s2, s3, s4] = [
-                    level.block(top.back()).is_solid(),
-                    level.block(top.right()).is_solid(),
-                    level.block(top.front()).is_solid(),
-                    level.block(top.left()).is_solid(),
-                ];
-                let [c1, c2, c3, c4] = [
-                    level.block(top.back().left()).is_solid(),
-                    level.block(top.back().right()).is_solid(),
-                    level.block(top.front().right()).is_solid(),
-                    level.block(top.front().left()).is_solid(),
-                ];
-                [
-                    calculate_corner_ao(s4, s3, c4), // front-left
-                    calculate_corner_ao(s2, s3, c3), // front-right
-                    calculate_corner_ao(s2, s1, c2), // back-right
-                    calculate_corner_ao(s4, s1, c1), // back-left
-                ]
-            }
-            VoxelFace::Bottom => {
-                let bottom = block_pos.bottom();
-
-                let [s1, s2, s3, s4] = [
-                    level.block(bottom.back()).is_solid(),
-                    level.block(bottom.right()).is_solid(),
-                    level.block(bottom.front()).is_solid(),
-                    level.block(bottom.left()).is_solid(),
-                ];
-                let [c1, c2, c3, c4] = [
-                    level.block(bottom.back().left()).is_solid(),
-                    level.block(bottom.back().right()).is_solid(),
-                    level.block(bottom.front().right()).is_solid(),
-                    level.block(bottom.front().left()).is_solid(),
-                ];
-                [
-                    calculate_corner_ao(s4, s3, c4), // front-left
-                    calculate_corner_ao(s2, s3, c3), // front-right
-                    calculate_corner_ao(s2, s1, c2), // back-right
-                    calculate_corner_ao(s4, s1, c1), // back-left
-                ]
-            }
-            VoxelFace::Left => {
-                let left = block_pos.left();
-
-                let [s1, s2, s3, s4] = [
-                    level.block(left.back()).is_solid(),
-                    level.block(left.top()).is_solid(),
-                    level.block(left.front()).is_solid(),
-                    level.block(left.bottom()).is_solid(),
-                ];
-                let [c1, c2, c3, c4] = [
-                    level.block(left.bottom().back()).is_solid(),
-                    level.block(left.top().back()).is_solid(),
-                    level.block(left.top().front()).is_solid(),
-                    level.block(left.bottom().front()).is_solid(),
-                ];
-                [
-                    calculate_corner_ao(s2, s1, c2), // top-back
-                    calculate_corner_ao(s4, s1, c1), // bottom-back
-                    calculate_corner_ao(s4, s3, c4), // bottom-front
-                    calculate_corner_ao(s2, s3, c3), // top-front
-                ]
-            }
-            VoxelFace::Right => {
-                let right = block_pos.right();
-
-                let [s1, s2, s3, s4] = [
-                    level.block(right.back()).is_solid(),
-                    level.block(right.top()).is_solid(),
-                    level.block(right.front()).is_solid(),
-                    level.block(right.bottom()).is_solid(),
-                ];
-                let [c1, c2, c3, c4] = [
-                    level.block(right.bottom().back()).is_solid(),
-                    level.block(right.top().back()).is_solid(),
-                    level.block(right.top().front()).is_solid(),
-                    level.block(right.bottom().front()).is_solid(),
-                ];
-
-                [
-                    calculate_corner_ao(s2, s1, c2), // top-back (TopLeft)
-                    calculate_corner_ao(s4, s1, c1), // bottom-back (BottomLeft)
-                    calculate_corner_ao(s4, s3, c4), // bottom-front (BottomRight)
-                    calculate_corner_ao(s2, s3, c3), // top-front (TopRight)
-                ]
-            }
-            VoxelFace::Front => {
-                let front = block_pos.front();
-
-                let [s1, s2, s3, s4] = [
-                    level.block(front.bottom()).is_solid(),
-                    level.block(front.right()).is_solid(),
-                    level.block(front.top()).is_solid(),
-                    level.block(front.left()).is_solid(),
-                ];
-                let [c1, c2, c3, c4] = [
-                    level.block(front.bottom().left()).is_solid(),
-                    level.block(front.bottom().right()).is_solid(),
-                    level.block(front.top().right()).is_solid(),
-                    level.block(front.top().left()).is_solid(),
-                ];
-                [
-                    calculate_corner_ao(s4, s3, c4), // top-left
-                    calculate_corner_ao(s4, s1, c1), // bottom-left
-                    calculate_corner_ao(s2, s1, c2), // bottom-right
-                    calculate_corner_ao(s2, s3, c3), // top-right
-                ]
-            }
-            VoxelFace::Back => {
-                let back = block_pos.back();
-
-                let [s1, s2, s3, s4] = [
-                    level.block(back.left()).is_solid(),
-                    level.block(back.top()).is_solid(),
-                    level.block(back.right()).is_solid(),
-                    level.block(back.bottom()).is_solid(),
-                ];
-                let [c1, c2, c3, c4] = [
-                    level.block(back.bottom().left()).is_solid(),
-                    level.block(back.bottom().right()).is_solid(),
-                    level.block(back.top().right()).is_solid(),
-                    level.block(back.top().left()).is_solid(),
-                ];
-                [
-                    calculate_corner_ao(s2, s1, c4), // top-left
-                    calculate_corner_ao(s4, s1, c1), // bottom-left
-                    calculate_corner_ao(s4, s3, c2), // bottom-right
-                    calculate_corner_ao(s2, s3, c3), // top-right
-                ]
-            }
+/// How much each corner of a block face is darkened by the blocks around it, from 0 for the
+/// darkest to 2 for not at all, in the order [`VoxelMesh::add_quad`] emits its corners.
+pub fn ambient_occlusion(
+    snapshot: &ChunkSnapshot,
+    block_pos: BlockPos,
+    face: VoxelFace,
+) -> [u32; 4] {
+    match face {
+        VoxelFace::Top => {
+            let top = block_pos.top();
+
+            let [s1, s2, s3, s4] = [
+                snapshot.block(top.back()).is_solid(),
+                snapshot.block(top.right()).is_solid(),
+                snapshot.block(top.front()).is_solid(),
+                snapshot.block(top.left()).is_solid(),
+            ];
+            let [c1, c2, c3, c4] = [
+                snapshot.block(top.back().left()).is_solid(),
+                snapshot.block(top.back().right()).is_solid(),
+                snapshot.block(top.front().right()).is_solid(),
+                snapshot.block(top.front().left()).is_solid(),
+            ];
+            [
+                calculate_corner_ao(s4, s3, c4), // front-left
+                calculate_corner_ao(s2, s3, c3), // front-right
+                calculate_corner_ao(s2, s1, c2), // back-right
+                calculate_corner_ao(s4, s1, c1), // back-left
+            ]
+        }
+        VoxelFace::Bottom => {
+            let bottom = block_pos.bottom();
+
+            let [s1, s2, s3, s4] = [
+                snapshot.block(bottom.back()).is_solid(),
+                snapshot.block(bottom.right()).is_solid(),
+                snapshot.block(bottom.front()).is_solid(),
+                snapshot.block(bottom.left()).is_solid(),
+            ];
+            let [c1, c2, c3, c4] = [
+                snapshot.block(bottom.back().left()).is_solid(),
+                snapshot.block(bottom.back().right()).is_solid(),
+                snapshot.block(bottom.front().right()).is_solid(),
+                snapshot.block(bottom.front().left()).is_solid(),
+            ];
+            [
+                calculate_corner_ao(s4, s3, c4), // front-left
+                calculate_corner_ao(s2, s3, c3), // front-right
+                calculate_corner_ao(s2, s1, c2), // back-right
+                calculate_corner_ao(s4, s1, c1), // back-left
+            ]
+        }
+        VoxelFace::Left => {
+            let left = block_pos.left();
+
+            let [s1, s2, s3, s4] = [
+                snapshot.block(left.back()).is_solid(),
+                snapshot.block(left.top()).is_solid(),
+                snapshot.block(left.front()).is_solid(),
+                snapshot.block(left.bottom()).is_solid(),
+            ];
+            let [c1, c2, c3, c4] = [
+                snapshot.block(left.bottom().back()).is_solid(),
+                snapshot.block(left.top().back()).is_solid(),
+                snapshot.block(left.top().front()).is_solid(),
+                snapshot.block(left.bottom().front()).is_solid(),
+            ];
+            [
+                calculate_corner_ao(s2, s1, c2), // top-back
+                calculate_corner_ao(s4, s1, c1), // bottom-back
+                calculate_corner_ao(s4, s3, c4), // bottom-front
+                calculate_corner_ao(s2, s3, c3), // top-front
+            ]
+        }
+        VoxelFace::Right => {
+            let right = block_pos.right();
+
+            let [s1, s2, s3, s4] = [
+                snapshot.block(right.back()).is_solid(),
+                snapshot.block(right.top()).is_solid(),
+                snapshot.block(right.front()).is_solid(),
+                snapshot.block(right.bottom()).is_solid(),
+            ];
+            let [c1, c2, c3, c4] = [
+                snapshot.block(right.bottom().back()).is_solid(),
+                snapshot.block(right.top().back()).is_solid(),
+                snapshot.block(right.top().front()).is_solid(),
+                snapshot.block(right.bottom().front()).is_solid(),
+            ];
+
+            [
+                calculate_corner_ao(s2, s1, c2), // top-back (TopLeft)
+                calculate_corner_ao(s4, s1, c1), // bottom-back (BottomLeft)
+                calculate_corner_ao(s4, s3, c4), // bottom-front (BottomRight)
+                calculate_corner_ao(s2, s3, c3), // top-front (TopRight)
+            ]
+        }
+        VoxelFace::Front => {
+            let front = block_pos.front();
+
+            let [s1, s2, s3, s4] = [
+                snapshot.block(front.bottom()).is_solid(),
+                snapshot.block(front.right()).is_solid(),
+                snapshot.block(front.top()).is_solid(),
+                snapshot.block(front.left()).is_solid(),
+            ];
+            let [c1, c2, c3, c4] = [
+                snapshot.block(front.bottom().left()).is_solid(),
+                snapshot.block(front.bottom().right()).is_solid(),
+                snapshot.block(front.top().right()).is_solid(),
+                snapshot.block(front.top().left()).is_solid(),
+            ];
+            [
+                calculate_corner_ao(s4, s3, c4), // top-left
+                calculate_corner_ao(s4, s1, c1), // bottom-left
+                calculate_corner_ao(s2, s1, c2), // bottom-right
+                calculate_corner_ao(s2, s3, c3), // top-right
+            ]
+        }
+        VoxelFace::Back => {
+            let back = block_pos.back();
+
+            let [s1, s2, s3, s4] = [
+                snapshot.block(back.left()).is_solid(),
+                snapshot.block(back.top()).is_solid(),
+                snapshot.block(back.right()).is_solid(),
+                snapshot.block(back.bottom()).is_solid(),
+            ];
+            let [c1, c2, c3, c4] = [
+                snapshot.block(back.bottom().left()).is_solid(),
+                snapshot.block(back.bottom().right()).is_solid(),
+                snapshot.block(back.top().right()).is_solid(),
+                snapshot.block(back.top().left()).is_solid(),
+            ];
+            [
+                calculate_corner_ao(s2, s1, c4), // top-left
+                calculate_corner_ao(s4, s1, c1), // bottom-left
+                calculate_corner_ao(s4, s3, c2), // bottom-right
+                calculate_corner_ao(s2, s3, c3), // top-right
+            ]
         }
     }
 }
@@ -294,3 +381,122 @@ fn calculate_corner_ao(side1: bool, side2: bool, corner: bool) -> u32 {
         _ => 1,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        block::Block,
+        chunk::Chunk,
+        position::{ChunkPos, LocalPos, CHUNK_INDICES},
+    };
+
+    /// A face of a single block: its direction, layer, position across the face, texture and
+    /// ambient occlusion.
+    type UnitFace = (u32, u32, [u32; 2], u32, [u32; 4]);
+
+    fn chunk_with(block_at: impl Fn(LocalPos) -> Block) -> Chunk {
+        let mut chunk = Chunk::new();
+
+        for index in 0..CHUNK_INDICES {
+            let pos = LocalPos::from_index(index);
+            chunk.set(pos, block_at(pos));
+        }
+
+        chunk.optimize();
+        chunk
+    }
+
+    fn render(chunk: &Chunk, mode: MeshingMode) -> VoxelMesh {
+        let chunk_pos = ChunkPos::new(0, 0, 0);
+        ChunkSnapshot::new(chunk_pos, |pos| (pos == chunk_pos).then_some(chunk)).render(mode)
+    }
+
+    /// Splits every quad back into the block faces it covers.
+    fn unit_faces(mesh: &VoxelMesh) -> HashSet<UnitFace> {
+        let mut faces = HashSet::new();
+
+        for quad in mesh.voxels.chunks_exact(4) {
+            let quad: [u32; 4] = quad.try_into().unwrap();
+            let decoded = quad.map(|voxel| {
+                let pos = [voxel >> 27, voxel >> 22, voxel >> 17].map(|axis| axis & 0x1F);
+                (pos, (voxel >> 14) & 0x7, (voxel >> 12) & 0x3, voxel & 0xFFF)
+            });
+
+            let (_, face, _, tex_index) = decoded[0];
+            let [normal, axis_a, axis_b] = VoxelFace::ALL[face as usize].axes();
+            let ao = decoded.map(|(_, _, ao, _)| ao);
+
+            let range = |axis: usize| {
+                let values = decoded.map(|(pos, _, _, _)| pos[axis]);
+                *values.iter().min().unwrap()..*values.iter().max().unwrap()
+            };
+
+            let (range_a, range_b) = (range(axis_a), range(axis_b));
+
+            if range_a.len() > 1 || range_b.len() > 1 {
+                assert!(ao.iter().all(|&value| value == ao[0]));
+            }
+
+            for a in range_a {
+                for b in range_b.clone() {
+                    faces.insert((face, decoded[0].0[normal], [a, b], tex_index, ao));
+                }
+            }
+        }
+
+        faces
+    }
+
+    #[test]
+    fn greedy_merges_flat_terrain() {
+        let chunk = chunk_with(|pos| if pos.y < 8 { Block::Rock } else { Block::Air });
+
+        assert_eq!(
+            render(&chunk, MeshingMode::PerFace).vertex_count(),
+            1024 * 4
+        );
+        assert_eq!(render(&chunk, MeshingMode::Greedy).vertex_count(), 6 * 4);
+    }
+
+    #[test]
+    fn greedy_keeps_textures_apart() {
+        let chunk = chunk_with(|pos| match (pos.y, pos.x % 2) {
+            (0, 0) => Block::Rock,
+            (0, _) => Block::Dirt,
+            _ => Block::Air,
+        });
+
+        let mesh = render(&chunk, MeshingMode::Greedy);
+        let top_quads = mesh
+            .voxels
+            .chunks(4)
+            .filter(|quad| (quad[0] >> 14) & 0x7 == VoxelFace::Top as u32)
+            .count();
+
+        assert_eq!(top_quads, CHUNK_SIZE);
+    }
+
+    #[test]
+    fn greedy_matches_per_face() {
+        let chunk = chunk_with(|pos| {
+            let hash = (pos.index() as u32).wrapping_mul(2_654_435_761) >> 24;
+
+            match pos.y {
+                0..4 => Block::Rock,
+                4 => Block::Grass,
+                5..10 if hash.is_multiple_of(5) => Block::Dirt,
+                5..10 if hash.is_multiple_of(7) => Block::Leaves,
+                _ => Block::Air,
+            }
+        });
+
+        let per_face = render(&chunk, MeshingMode::PerFace);
+        let greedy = render(&chunk, MeshingMode::Greedy);
+
+        assert!(greedy.vertex_count() < per_face.vertex_count());
+        assert_eq!(unit_faces(&greedy), unit_faces(&per_face));
+    }
+}